@@ -0,0 +1,146 @@
+//! Append-only audit log of the management actions, written in JSON Lines.
+use serde_derive::{Deserialize, Serialize};
+use std::{
+    fs::{self, OpenOptions},
+    io::{self, Write},
+    path::PathBuf,
+};
+
+/// `[audit]` が設定されていない場合の監査ログのパス
+pub const DEFAULT_AUDIT_PATH: &str = "audit.jsonl";
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct AuditEntry {
+    /// RFC 3339形式の日時
+    pub timestamp: String,
+    pub user_id: u64,
+    pub user_tag: String,
+    /// 対象のサーバの名前
+    #[serde(default)]
+    pub server: String,
+    pub command: String,
+    pub args: Vec<String>,
+    pub result: AuditResult,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum AuditResult {
+    /// 受け付けて実行した
+    Accepted,
+    /// 権限がなかった
+    Denied,
+    /// `!mcc` の制限で拒否した
+    Rejected,
+    /// 他のユーザの承認を待っている
+    PendingApproval,
+    /// 他のユーザが承認して実行した
+    Approved,
+    /// 受け付けたが、実行できなかった
+    Failed,
+}
+
+impl AuditResult {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AuditResult::Accepted => "accepted",
+            AuditResult::Denied => "denied",
+            AuditResult::Rejected => "rejected",
+            AuditResult::PendingApproval => "pending_approval",
+            AuditResult::Approved => "approved",
+            AuditResult::Failed => "failed",
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct AuditLog {
+    path: PathBuf,
+}
+
+impl AuditLog {
+    pub fn new(path: impl Into<PathBuf>) -> AuditLog {
+        AuditLog { path: path.into() }
+    }
+
+    /// Append an entry to the end of the file.
+    pub fn append(&self, entry: &AuditEntry) -> io::Result<()> {
+        let mut line = serde_json::to_string(entry)?;
+        line.push('\n');
+
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        // 1行を1回で書き込む
+        file.write_all(line.as_bytes())
+    }
+
+    /// Read the latest `n` entries, oldest first.
+    ///
+    /// Lines which cannot be parsed are skipped.
+    pub fn latest(&self, n: usize) -> io::Result<Vec<AuditEntry>> {
+        let content = match fs::read_to_string(&self.path) {
+            Ok(content) => content,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(err) => return Err(err),
+        };
+
+        let entries: Vec<AuditEntry> = content
+            .lines()
+            .filter_map(|line| serde_json::from_str(line).ok())
+            .collect();
+        let skip = entries.len().saturating_sub(n);
+
+        Ok(entries.into_iter().skip(skip).collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(command: &str, result: AuditResult) -> AuditEntry {
+        AuditEntry {
+            timestamp: "2025-01-01T12:00:00+09:00".to_string(),
+            user_id: 12345678987654321,
+            user_tag: "steve".to_string(),
+            server: "default".to_string(),
+            command: command.to_string(),
+            args: vec!["say".to_string(), "hello".to_string()],
+            result,
+        }
+    }
+
+    #[test]
+    fn append_and_read_latest() {
+        let dir = std::env::temp_dir().join(format!("hone-test-audit-{}", std::process::id()));
+        fs::remove_dir_all(&dir).ok();
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("audit.jsonl");
+        let log = AuditLog::new(&path);
+
+        assert!(log.latest(10).unwrap().is_empty());
+
+        log.append(&entry("mcstart", AuditResult::Accepted))
+            .unwrap();
+        log.append(&entry("mcc", AuditResult::Rejected)).unwrap();
+        log.append(&entry("mcsvend", AuditResult::Denied)).unwrap();
+
+        let latest = log.latest(2).unwrap();
+        assert_eq!(
+            latest,
+            vec![
+                entry("mcc", AuditResult::Rejected),
+                entry("mcsvend", AuditResult::Denied)
+            ]
+        );
+        assert_eq!(log.latest(10).unwrap().len(), 3);
+
+        let content = fs::read_to_string(&path).unwrap();
+        assert_eq!(content.lines().count(), 3);
+        assert!(content.contains(r#""result":"rejected""#));
+
+        fs::remove_dir_all(&dir).ok();
+    }
+}
@@ -1,19 +1,44 @@
+use crate::save::retention::parse_size;
+use crate::schedule::{parse_duration, TimeRange};
 use serde_derive::Deserialize;
-use serenity::all::{ChannelId, UserId};
-use std::{fs, path::PathBuf};
+use serenity::all::{ChannelId, RoleId, UserId};
+use std::{collections::HashMap, fs, path::PathBuf, time::Duration};
+
+/// `[server]` で名前が指定されていない場合のサーバの名前
+const DEFAULT_SERVER_NAME: &str = "default";
+
+/// 自動停止するまでの既定の時間 (分)
+const DEFAULT_IDLE_MINUTES: u64 = 3;
+
+/// Minecraftサーバの既定のポート
+const DEFAULT_SERVER_PORT: u16 = 25565;
+
+/// RCONの既定のポート
+const DEFAULT_RCON_PORT: u16 = 25575;
+
+/// 再起動を予告する既定のタイミング (秒)
+const DEFAULT_RESTART_WARNINGS: [u64; 4] = [600, 300, 60, 10];
 
 #[derive(Deserialize, Clone)]
 pub struct Config {
     pub client: ClientConfig,
     pub permission: PermissionConfig,
-    pub server: ServerConfig,
+    /// サーバを1つだけ管理する場合の設定
+    pub server: Option<ServerConfig>,
+    /// 複数のサーバを管理する場合の設定
+    #[serde(default)]
+    pub servers: Vec<ServerConfig>,
     pub backup: Option<BackupConfig>,
+    pub audit: Option<AuditConfig>,
+    pub bridge: Option<BridgeConfig>,
 }
 
 /// Discordクライアントの設定
 #[derive(Deserialize, Clone)]
 pub struct ClientConfig {
     pub secret: String,
+    /// `!` から始まるメッセージでの操作を受け付けるか (既定では受け付ける)
+    pub message_commands: Option<bool>,
 }
 
 /// 権限の設定
@@ -22,25 +47,392 @@ pub struct PermissionConfig {
     /// コマンドを送信できるチャンネル
     pub channel_id: ChannelId,
 
-    /// コマンドを実行できるユーザ
+    /// すべてのコマンドを実行できるユーザ
+    #[serde(default)]
     pub user_id: Vec<UserId>,
+
+    /// ギルドの全員に与える権限
+    pub everyone: Option<PermissionLevel>,
+
+    pub viewer: Option<PermissionGrant>,
+    pub operator: Option<PermissionGrant>,
+    pub admin: Option<PermissionGrant>,
+
+    /// コマンドごとに必要な権限を変更する
+    #[serde(default)]
+    pub commands: HashMap<String, PermissionLevel>,
+
+    /// `!mcc` で実行できるコマンドの制限
+    pub console: Option<ConsoleConfig>,
+}
+
+/// `!mcc` で実行できるコマンドの設定
+#[derive(Deserialize, Clone)]
+pub struct ConsoleConfig {
+    /// 実行できるコマンドのパターン。省略時は `deny` 以外のすべて
+    pub allow: Option<Vec<String>>,
+
+    /// 実行できないコマンドのパターン
+    #[serde(default)]
+    pub deny: Vec<String>,
+
+    /// 実行に他のユーザの承認が必要なコマンドのパターン
+    #[serde(default)]
+    pub approval: Vec<String>,
+
+    /// 承認を待つ時間 (秒)。省略時は300秒
+    pub approval_timeout: Option<u64>,
+}
+
+/// 権限の段階。後のものほど強い
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "lowercase")]
+pub enum PermissionLevel {
+    Viewer,
+    Operator,
+    Admin,
+}
+
+/// 権限を与えるユーザとロール
+#[derive(Deserialize, Clone, Default)]
+pub struct PermissionGrant {
+    #[serde(default)]
+    pub users: Vec<UserId>,
+    #[serde(default)]
+    pub roles: Vec<RoleId>,
+}
+
+impl PermissionGrant {
+    fn contains(&self, user: UserId, roles: &[RoleId]) -> bool {
+        self.users.contains(&user) || roles.iter().any(|role| self.roles.contains(role))
+    }
+}
+
+impl PermissionConfig {
+    /// Get the highest permission level of the user, or `None` if the user has no permission.
+    pub fn level_of(&self, user: UserId, roles: &[RoleId]) -> Option<PermissionLevel> {
+        if self.user_id.contains(&user) {
+            return Some(PermissionLevel::Admin);
+        }
+
+        let grants = [
+            (PermissionLevel::Admin, &self.admin),
+            (PermissionLevel::Operator, &self.operator),
+            (PermissionLevel::Viewer, &self.viewer),
+        ];
+        grants
+            .into_iter()
+            .find(|(_, grant)| grant.as_ref().is_some_and(|g| g.contains(user, roles)))
+            .map(|(level, _)| level)
+            .or(self.everyone)
+    }
+
+    /// Get the permission level required to run the command.
+    pub fn required_level(&self, command: &str) -> PermissionLevel {
+        if let Some(level) = self.commands.get(command) {
+            return *level;
+        }
+
+        match command {
+            "mcstart" | "mcstatus" | "mcplayers" => PermissionLevel::Viewer,
+            "mcc" | "mcend" | "mcrestart" | "mcbackup" | "mcautostop" => PermissionLevel::Operator,
+            // mcsvend, mckill, mcrestore, mcaudit と未知のコマンド
+            _ => PermissionLevel::Admin,
+        }
+    }
 }
 
 /// Minecraftサーバの設定
 #[derive(Deserialize, Clone)]
 pub struct ServerConfig {
+    /// コマンドで指定するサーバの名前。`[[servers]]` では必須
+    pub name: Option<String>,
+    /// 操作を受け付けるチャンネル。省略時は `permission.channel_id`
+    pub channel_id: Option<ChannelId>,
     pub work_dir: PathBuf,
-    #[cfg(target_os = "windows")]
+    /// 省略時は `25565`
+    #[serde(default = "default_server_port")]
+    pub port: u16,
+    /// サーバのjarファイル。`argfiles` を指定する場合は不要
+    pub jar_file: Option<PathBuf>,
+    /// jarファイルの代わりに `java` に渡す引数ファイル (Forge/NeoForgeの `@user_jvm_args.txt` など)
+    #[serde(default)]
+    pub argfiles: Vec<PathBuf>,
+    /// jarファイルの代わりにシェルで実行する起動スクリプト (`run.sh` など)
+    pub launch_script: Option<PathBuf>,
+    /// プレイヤーがいないときの自動停止。`true` または設定のテーブル
+    pub auto_stop: AutoStopSetting,
+    /// ヒープの初期サイズと最大サイズ
+    pub memory: Option<String>,
+    /// ヒープの初期サイズ。省略時は `memory`
+    pub min_memory: Option<String>,
+    /// ヒープの最大サイズ。省略時は `memory`
+    pub max_memory: Option<String>,
+    /// `java` のパス。省略時は `PATH` から探す
+    pub java_path: Option<PathBuf>,
+    /// JVMに渡す引数
+    #[serde(default)]
+    pub jvm_args: Vec<String>,
+    /// `nogui` に続けてサーバに渡す引数
+    #[serde(default)]
+    pub server_args: Vec<String>,
+    /// サーバに設定する環境変数
+    #[serde(default)]
+    pub env: HashMap<String, String>,
+    /// `stop` を送信してから終了を待つ時間 (秒)。省略時は60秒
+    pub stop_timeout: Option<u64>,
+    pub restart: Option<RestartConfig>,
+    /// 定期再起動の設定
+    pub scheduled_restart: Option<ScheduledRestartConfig>,
+    /// 停止中にプレイヤーが接続したらサーバを起動する設定
+    pub wake_on_connect: Option<WakeConfig>,
+    pub rcon: Option<RconConfig>,
+    /// このサーバのバックアップ設定。省略時は `[backup]` を使う
+    pub backup: Option<BackupConfig>,
+    /// このサーバのチャットブリッジの設定
+    pub bridge: Option<BridgeConfig>,
+}
+
+fn default_server_port() -> u16 {
+    DEFAULT_SERVER_PORT
+}
+
+impl ServerConfig {
+    pub fn name(&self) -> &str {
+        self.name.as_deref().unwrap_or(DEFAULT_SERVER_NAME)
+    }
+
+    pub fn min_memory(&self) -> Option<&str> {
+        self.min_memory.as_deref().or(self.memory.as_deref())
+    }
+
+    pub fn max_memory(&self) -> Option<&str> {
+        self.max_memory.as_deref().or(self.memory.as_deref())
+    }
+
+    /// Get the auto-stop settings, or `None` if auto-stop is disabled.
+    pub fn auto_stop(&self) -> Option<AutoStopConfig> {
+        match self.auto_stop {
+            AutoStopSetting::Enabled(true) => Some(AutoStopConfig::default()),
+            AutoStopSetting::Enabled(false) => None,
+            AutoStopSetting::Config(ref config) => Some(config.clone()),
+        }
+    }
+
+    /// Offsets before a restart to warn the players at, in descending order.
+    pub fn restart_warnings(&self) -> Result<Vec<Duration>, String> {
+        let mut warnings = match self
+            .scheduled_restart
+            .as_ref()
+            .and_then(|r| r.warnings.as_ref())
+        {
+            Some(warnings) => warnings
+                .iter()
+                .map(|w| parse_duration(w))
+                .collect::<Result<Vec<_>, _>>()?,
+            None => DEFAULT_RESTART_WARNINGS
+                .iter()
+                .map(|secs| Duration::from_secs(*secs))
+                .collect(),
+        };
+        warnings.sort_unstable_by(|a, b| b.cmp(a));
+        warnings.dedup();
+        Ok(warnings)
+    }
+
+    /// Files which must exist to launch the server, relative to `work_dir`.
+    pub fn launch_files(&self) -> Vec<PathBuf> {
+        self.jar_file
+            .iter()
+            .chain(self.launch_script.iter())
+            .chain(self.argfiles.iter())
+            .cloned()
+            .collect()
+    }
+}
+
+/// 自動停止の設定。`auto_stop = true` とテーブルのどちらでも指定できる
+#[derive(Deserialize, Clone)]
+#[serde(untagged)]
+pub enum AutoStopSetting {
+    Enabled(bool),
+    Config(AutoStopConfig),
+}
+
+/// 自動停止の設定
+#[derive(Deserialize, Clone)]
+pub struct AutoStopConfig {
+    /// プレイヤーがいない状態が続いたら停止するまでの時間 (分)
+    #[serde(default = "default_idle_minutes")]
+    pub idle_minutes: u64,
+    /// 起動してから自動停止しない時間 (分)
+    #[serde(default)]
+    pub grace_minutes: u64,
+    /// 停止する何秒前にゲーム内とチャンネルで予告するか
+    pub warning_seconds: Option<u64>,
+    /// 自動停止しない時間帯 (例: "18:00-23:00")
+    #[serde(default)]
+    pub keep_running: Vec<String>,
+    /// 停止する前にバックアップを作成する
+    #[serde(default)]
+    pub backup: bool,
+}
+
+fn default_idle_minutes() -> u64 {
+    DEFAULT_IDLE_MINUTES
+}
+
+impl Default for AutoStopConfig {
+    fn default() -> Self {
+        AutoStopConfig {
+            idle_minutes: DEFAULT_IDLE_MINUTES,
+            grace_minutes: 0,
+            warning_seconds: None,
+            keep_running: Vec::new(),
+            backup: false,
+        }
+    }
+}
+
+impl AutoStopConfig {
+    /// Parse the hours in which the server is kept running.
+    pub fn keep_running(&self) -> Result<Vec<TimeRange>, String> {
+        self.keep_running
+            .iter()
+            .map(|range| TimeRange::parse(range))
+            .collect()
+    }
+}
+
+/// RCONの設定
+#[derive(Deserialize, Clone)]
+pub struct RconConfig {
+    /// 接続先のホスト。省略時は `127.0.0.1`
+    pub host: Option<String>,
+    /// 省略時は `25575`
+    #[serde(default = "default_rcon_port")]
     pub port: u16,
-    pub jar_file: PathBuf,
-    pub auto_stop: bool,
-    pub memory: String,
+    pub password: String,
+}
+
+fn default_rcon_port() -> u16 {
+    DEFAULT_RCON_PORT
+}
+
+impl RconConfig {
+    pub fn address(&self) -> (String, u16) {
+        let host = self.host.as_deref().unwrap_or("127.0.0.1");
+        (host.to_string(), self.port)
+    }
+}
+
+/// クラッシュ時の自動再起動の設定
+#[derive(Deserialize, Clone)]
+pub struct RestartConfig {
+    /// 連続して再起動を試みる最大回数
+    pub max_attempts: u32,
+
+    /// 最初の再起動までの待機時間 (秒)。再起動のたびに2倍になる
+    pub delay: u64,
+}
+
+impl RestartConfig {
+    /// `attempt` 回目 (1始まり) の再起動までの待機時間
+    pub fn delay_for(&self, attempt: u32) -> std::time::Duration {
+        let factor = 2u64.saturating_pow(attempt.saturating_sub(1));
+        std::time::Duration::from_secs(self.delay.saturating_mul(factor))
+    }
+}
+
+/// 定期再起動の設定
+#[derive(Deserialize, Clone)]
+pub struct ScheduledRestartConfig {
+    /// 再起動の間隔 (分)
+    pub interval: Option<u64>,
+    /// 再起動の日時 (cron形式: 分 時 日 月 曜日)
+    pub cron: Option<String>,
+    /// 再起動をゲーム内で予告するタイミング (例: "10m", "10s")
+    pub warnings: Option<Vec<String>>,
+}
+
+/// プレイヤーの接続でサーバを起動する設定
+#[derive(Deserialize, Clone)]
+pub struct WakeConfig {
+    /// 停止中にサーバ一覧に表示するMOTD
+    pub motd: Option<String>,
+    /// 接続したプレイヤーに表示するメッセージ
+    pub kick_message: Option<String>,
+}
+
+/// チャットブリッジの設定
+#[derive(Deserialize, Clone)]
+pub struct BridgeConfig {
+    /// ゲーム内のチャットを中継するチャンネル
+    pub channel_id: ChannelId,
+
+    /// 設定すると、Webhookを使ってプレイヤーの名前でチャットを送信する
+    pub webhook_url: Option<String>,
+}
+
+/// 監査ログの設定
+#[derive(Deserialize, Clone)]
+pub struct AuditConfig {
+    /// 監査ログを書き込むファイル (JSON Lines)
+    pub path: PathBuf,
 }
 
 /// バックアップ設定
 #[derive(Deserialize, Clone)]
 pub struct BackupConfig {
     pub output_dir: PathBuf,
+    pub retention: Option<RetentionConfig>,
+    pub schedule: Option<BackupScheduleConfig>,
+    /// ワールド以外にバックアップするパス (作業ディレクトリからの相対パス)
+    pub include: Option<Vec<PathBuf>>,
+    /// バックアップから除外するパターン。`session.lock` は常に除外する
+    pub exclude: Option<Vec<String>>,
+}
+
+/// 定期バックアップの設定
+#[derive(Deserialize, Clone)]
+pub struct BackupScheduleConfig {
+    /// 実行間隔 (分)
+    pub interval: Option<u64>,
+    /// 実行日時 (cron形式: 分 時 日 月 曜日)
+    pub cron: Option<String>,
+    /// 前回のバックアップ以降にプレイヤーが参加した場合だけ実行する
+    #[serde(default)]
+    pub only_if_players: bool,
+    /// サーバの停止時にも実行する
+    #[serde(default)]
+    pub on_stop: bool,
+}
+
+/// バックアップの保持設定
+///
+/// いずれかの `keep_*` を指定すると、どの条件にも当てはまらないバックアップは削除されます。
+#[derive(Deserialize, Clone)]
+pub struct RetentionConfig {
+    /// 新しいものから残す数
+    pub keep_last: Option<usize>,
+    /// 日ごとに1つ残す日数
+    pub keep_daily: Option<usize>,
+    /// 週ごとに1つ残す週数
+    pub keep_weekly: Option<usize>,
+    /// 月ごとに1つ残す月数
+    pub keep_monthly: Option<usize>,
+    /// バックアップの合計サイズの上限 (例: "50G")
+    pub max_total_size: Option<String>,
+}
+
+impl RetentionConfig {
+    /// Get the limit of the total size of the backups in bytes.
+    pub fn max_total_size(&self) -> Result<Option<u64>, String> {
+        self.max_total_size
+            .as_deref()
+            .map(|size| parse_size(size).ok_or_else(|| format!("サイズの形式が不正です: {size:?}")))
+            .transpose()
+    }
 }
 
 impl Config {
@@ -55,4 +447,413 @@ impl Config {
             Err(err) => Err(format!("設定に誤りがあります: {}", err)),
         }
     }
+
+    /// Get the settings of each managed server, filled in with the shared settings.
+    ///
+    /// With `[[servers]]`, the shared `[backup]` is used with a subdirectory named after
+    /// the server, and the shared `[bridge]` is not used.
+    pub fn managed_servers(&self) -> Result<Vec<ServerConfig>, String> {
+        let multiple = !self.servers.is_empty();
+        let servers = match self.server {
+            Some(_) if multiple => {
+                return Err("[server] と [[servers]] はどちらか一方だけ指定してください".to_string())
+            }
+            Some(ref server) => vec![server.clone()],
+            None if multiple => self.servers.clone(),
+            None => return Err("サーバが設定されていません".to_string()),
+        };
+
+        if let Some(retention) = self.backup.as_ref().and_then(|b| b.retention.as_ref()) {
+            retention
+                .max_total_size()
+                .map_err(|err| format!("backup.retention.max_total_size: {err}"))?;
+        }
+
+        let mut names: Vec<&str> = Vec::new();
+        for server in &servers {
+            let name = match server.name {
+                Some(ref name) => name.as_str(),
+                None if multiple => {
+                    return Err("[[servers]] には name を指定してください".to_string())
+                }
+                None => DEFAULT_SERVER_NAME,
+            };
+            if name.is_empty() || name.contains(char::is_whitespace) {
+                return Err(format!("サーバの名前に空白は使えません: {name:?}"));
+            }
+            if names.contains(&name) {
+                return Err(format!("サーバの名前が重複しています: {name}"));
+            }
+            names.push(name);
+
+            let launchers = [
+                server.jar_file.is_some(),
+                !server.argfiles.is_empty(),
+                server.launch_script.is_some(),
+            ];
+            if launchers.into_iter().filter(|set| *set).count() != 1 {
+                return Err(format!(
+                    "{name}: jar_file, argfiles, launch_script のいずれか1つを指定してください"
+                ));
+            }
+
+            server
+                .restart_warnings()
+                .map_err(|err| format!("{name}: scheduled_restart.warnings: {err}"))?;
+
+            if let Some(retention) = server.backup.as_ref().and_then(|b| b.retention.as_ref()) {
+                retention
+                    .max_total_size()
+                    .map_err(|err| format!("{name}: backup.retention.max_total_size: {err}"))?;
+            }
+
+            if let Some(auto_stop) = server.auto_stop() {
+                if auto_stop.idle_minutes == 0 {
+                    return Err(format!(
+                        "{name}: auto_stop.idle_minutes には1以上を指定してください"
+                    ));
+                }
+                auto_stop
+                    .keep_running()
+                    .map_err(|err| format!("{name}: auto_stop.keep_running: {err}"))?;
+            }
+        }
+
+        Ok(servers
+            .into_iter()
+            .map(|server| {
+                let backup = server.backup.clone().or_else(|| {
+                    let mut backup = self.backup.clone()?;
+                    if multiple {
+                        backup.output_dir = backup.output_dir.join(server.name());
+                    }
+                    Some(backup)
+                });
+                let bridge = match server.bridge {
+                    Some(_) => server.bridge.clone(),
+                    None if multiple => None,
+                    None => self.bridge.clone(),
+                };
+
+                ServerConfig {
+                    channel_id: Some(server.channel_id.unwrap_or(self.permission.channel_id)),
+                    backup,
+                    bridge,
+                    ..server
+                }
+            })
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn permission() -> PermissionConfig {
+        toml::from_str(
+            r#"
+            channel_id = 1
+            user_id = [10]
+            everyone = "viewer"
+
+            [operator]
+            roles = [100]
+
+            [admin]
+            users = [20]
+
+            [commands]
+            mcbackup = "admin"
+            "#,
+        )
+        .unwrap()
+    }
+
+    fn config(servers: &str) -> Config {
+        toml::from_str(&format!(
+            r#"
+            [client]
+            secret = "TOKEN"
+
+            [permission]
+            channel_id = 1
+
+            [backup]
+            output_dir = "backups"
+
+            {servers}
+            "#
+        ))
+        .unwrap()
+    }
+
+    #[test]
+    fn default_ports() {
+        let config = config(
+            r#"
+            [server]
+            work_dir = "srv"
+            jar_file = "server.jar"
+            auto_stop = false
+            memory = "4G"
+
+            [server.rcon]
+            password = "PASSWORD"
+            "#,
+        );
+
+        let servers = config.managed_servers().unwrap();
+        assert_eq!(servers[0].port, 25565);
+        assert_eq!(
+            servers[0].rcon.as_ref().unwrap().address(),
+            ("127.0.0.1".to_string(), 25575)
+        );
+    }
+
+    #[test]
+    fn single_server() {
+        let config = config(
+            r#"
+            [server]
+            work_dir = "srv"
+            port = 25565
+            jar_file = "server.jar"
+            auto_stop = false
+            memory = "4G"
+            "#,
+        );
+
+        let servers = config.managed_servers().unwrap();
+        assert_eq!(servers.len(), 1);
+        assert_eq!(servers[0].name(), "default");
+        assert_eq!(servers[0].channel_id, Some(ChannelId::new(1)));
+        assert_eq!(
+            servers[0].backup.as_ref().unwrap().output_dir,
+            PathBuf::from("backups")
+        );
+    }
+
+    #[test]
+    fn multiple_servers() {
+        let config = config(
+            r#"
+            [[servers]]
+            name = "survival"
+            work_dir = "survival"
+            port = 25565
+            jar_file = "server.jar"
+            auto_stop = true
+            memory = "4G"
+
+            [[servers]]
+            name = "creative"
+            channel_id = 2
+            work_dir = "creative"
+            port = 25566
+            jar_file = "server.jar"
+            auto_stop = false
+            memory = "2G"
+            "#,
+        );
+
+        let servers = config.managed_servers().unwrap();
+        assert_eq!(servers[0].channel_id, Some(ChannelId::new(1)));
+        assert_eq!(servers[1].channel_id, Some(ChannelId::new(2)));
+        // バックアップはサーバごとのフォルダに保存する
+        assert_eq!(
+            servers[1].backup.as_ref().unwrap().output_dir,
+            PathBuf::from("backups").join("creative")
+        );
+    }
+
+    #[test]
+    fn reject_invalid_servers() {
+        let server = |name: &str| {
+            format!(
+                r#"
+                [[servers]]
+                {name}
+                work_dir = "srv"
+                port = 25565
+                jar_file = "server.jar"
+                auto_stop = false
+                memory = "4G"
+                "#
+            )
+        };
+
+        assert!(config("").managed_servers().is_err());
+        assert!(config(&server("")).managed_servers().is_err());
+        assert!(config(&server(r#"name = "a b""#))
+            .managed_servers()
+            .is_err());
+        assert!(
+            config(&(server(r#"name = "a""#) + &server(r#"name = "a""#)))
+                .managed_servers()
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn invalid_max_total_size() {
+        let server = r#"
+            [server]
+            work_dir = "srv"
+            port = 25565
+            jar_file = "server.jar"
+            auto_stop = false
+            "#;
+        let config = |retention: &str| -> Config {
+            toml::from_str(&format!(
+                r#"
+                [client]
+                secret = "TOKEN"
+
+                [permission]
+                channel_id = 1
+
+                [backup]
+                output_dir = "backups"
+
+                [backup.retention]
+                max_total_size = "{retention}"
+
+                {server}
+                "#
+            ))
+            .unwrap()
+        };
+
+        assert!(config("10 GB").managed_servers().is_ok());
+        assert!(config("10GiB").managed_servers().is_err());
+        assert!(config("ten G").managed_servers().is_err());
+    }
+
+    #[test]
+    fn restart_warnings() {
+        let server = |restart: &str| {
+            let config = config(&format!(
+                r#"
+                [server]
+                work_dir = "srv"
+                port = 25565
+                jar_file = "server.jar"
+                auto_stop = false
+                {restart}
+                "#
+            ));
+            config.server.unwrap()
+        };
+
+        let secs = |s: &[u64]| {
+            s.iter()
+                .map(|s| Duration::from_secs(*s))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(server("").restart_warnings(), Ok(secs(&[600, 300, 60, 10])));
+        assert_eq!(
+            server("[server.scheduled_restart]\nwarnings = [\"30s\", \"2m\"]").restart_warnings(),
+            Ok(secs(&[120, 30]))
+        );
+        assert!(server("[server.scheduled_restart]\nwarnings = [\"2x\"]")
+            .restart_warnings()
+            .is_err());
+    }
+
+    #[test]
+    fn auto_stop_settings() {
+        let server = |auto_stop: &str| {
+            let config = config(&format!(
+                r#"
+                [server]
+                work_dir = "srv"
+                port = 25565
+                jar_file = "server.jar"
+                {auto_stop}
+                "#
+            ));
+            config.server.unwrap()
+        };
+
+        assert!(server("auto_stop = false").auto_stop().is_none());
+        assert_eq!(
+            server("auto_stop = true").auto_stop().unwrap().idle_minutes,
+            3
+        );
+
+        let auto_stop = server(
+            r#"
+            [server.auto_stop]
+            idle_minutes = 10
+            keep_running = ["18:00-23:00"]
+            backup = true
+            "#,
+        )
+        .auto_stop()
+        .unwrap();
+        assert_eq!(auto_stop.idle_minutes, 10);
+        assert_eq!(auto_stop.grace_minutes, 0);
+        assert_eq!(auto_stop.keep_running().unwrap().len(), 1);
+        assert!(auto_stop.backup);
+    }
+
+    #[test]
+    fn level_of_user() {
+        let permission = permission();
+
+        assert_eq!(
+            permission.level_of(UserId::new(10), &[]),
+            Some(PermissionLevel::Admin)
+        );
+        assert_eq!(
+            permission.level_of(UserId::new(20), &[]),
+            Some(PermissionLevel::Admin)
+        );
+        assert_eq!(
+            permission.level_of(UserId::new(30), &[RoleId::new(100)]),
+            Some(PermissionLevel::Operator)
+        );
+        assert_eq!(
+            permission.level_of(UserId::new(30), &[RoleId::new(200)]),
+            Some(PermissionLevel::Viewer)
+        );
+
+        let permission = PermissionConfig {
+            everyone: None,
+            ..permission
+        };
+        assert_eq!(permission.level_of(UserId::new(30), &[]), None);
+    }
+
+    #[test]
+    fn required_level_of_command() {
+        let permission = permission();
+
+        assert_eq!(
+            permission.required_level("mcstart"),
+            PermissionLevel::Viewer
+        );
+        assert_eq!(
+            permission.required_level("mcplayers"),
+            PermissionLevel::Viewer
+        );
+        assert_eq!(permission.required_level("mcc"), PermissionLevel::Operator);
+        assert_eq!(
+            permission.required_level("mcrestart"),
+            PermissionLevel::Operator
+        );
+        assert_eq!(
+            permission.required_level("mcautostop"),
+            PermissionLevel::Operator
+        );
+        for command in ["mcsvend", "mckill", "mcrestore", "mcaudit"] {
+            assert_eq!(permission.required_level(command), PermissionLevel::Admin);
+        }
+        // 設定で変更できる
+        assert_eq!(
+            permission.required_level("mcbackup"),
+            PermissionLevel::Admin
+        );
+    }
 }
@@ -1,125 +1,226 @@
 use self::command::*;
-use crate::config::Config;
-use crate::save::backup::save_backup;
-use serenity::all::UserId;
+use crate::audit::{AuditLog, AuditResult, DEFAULT_AUDIT_PATH};
+use crate::config::{Config, ServerConfig};
+use crate::server::{AutoStopState, PlayerRegistry, WakeListener};
+use crate::types::{ServerProcess, StopReasonRecorder};
+use serenity::all::{MessageId, RoleId, User, UserId, Webhook};
 use serenity::http::Http;
 use serenity::model::channel::Message;
-use serenity::model::gateway::Ready;
 use serenity::model::prelude::ChannelId;
-use serenity::model::prelude::ChannelType;
 use serenity::prelude::*;
+use std::collections::HashMap;
 use std::process::exit;
+use std::sync::atomic::{AtomicBool, AtomicU32};
 use std::sync::{mpsc, Arc};
+use tokio::sync::Notify;
 
+mod approval;
+use approval::PendingApproval;
+mod audit;
+mod auto_stop;
+mod backup;
+mod bridge;
+use backup::PendingRestore;
 mod command;
+mod command_policy;
+use command_policy::CommandPolicy;
 mod log_sender;
 mod observer;
+mod players;
+mod restart;
+mod router;
+pub use router::Router;
+mod scheduler;
+mod slash;
+mod wake;
 use log_sender::*;
 
 type ArcMutex<T> = Arc<Mutex<T>>;
 
+#[derive(Clone)]
 pub struct Handler {
     config: Config,
+    /// このハンドラが管理するサーバの設定
+    server: ServerConfig,
     http: Arc<Http>,
     thread_stdin: ArcMutex<Option<mpsc::Sender<String>>>,
+    /// 起動中のサーバのプロセス
+    process: ArcMutex<Option<ServerProcess>>,
     log_thread: ArcMutex<Option<LogSessionGuildChannel>>,
+    /// サーバの終了を通知する
+    server_exit: Arc<Notify>,
+    /// `save-all` による保存の完了を通知する
+    game_saved: Arc<Notify>,
+    /// バックアップの同時実行を防ぐ
+    backup_lock: ArcMutex<()>,
+    /// 確認待ちの復元
+    pending_restore: ArcMutex<Option<PendingRestore>>,
+    /// オンラインのプレイヤー
+    players: PlayerRegistry,
+    /// 自動停止の一時停止と残り時間
+    auto_stop: AutoStopState,
+    /// 前回のバックアップ以降にプレイヤーが参加したか
+    played_since_backup: Arc<AtomicBool>,
+    /// 自動停止の前にバックアップを作成できたか
+    backed_up_before_auto_stop: Arc<AtomicBool>,
+    /// サーバを停止させた理由
+    stop_reason: StopReasonRecorder,
+    /// 連続してクラッシュした回数
+    crash_count: Arc<AtomicU32>,
+    /// 再起動を予定しているか
+    restart_pending: Arc<AtomicBool>,
+    /// 停止中にポートで接続を待ち受ける
+    wake_listener: ArcMutex<Option<WakeListener>>,
+    /// `!mcc` で実行できるコマンドの制限
+    command_policy: Option<Arc<CommandPolicy>>,
+    /// 承認待ちのコマンド
+    pending_approvals: ArcMutex<HashMap<MessageId, PendingApproval>>,
+    audit_log: AuditLog,
+    /// チャットブリッジで使うWebhook
+    bridge_webhook: ArcMutex<Option<Webhook>>,
 }
 
 impl Handler {
-    pub fn new(config: Config) -> Handler {
+    pub fn new(config: Config, server: ServerConfig) -> Handler {
         let stdin = Arc::new(Mutex::new(None));
         let http = Arc::new(Http::new(&config.client.secret));
+        let command_policy = config.permission.console.as_ref().map(|console| {
+            let policy = CommandPolicy::new(console).unwrap_or_else(|err| {
+                println!("permission.console の設定が不正です: {err}");
+                exit(-1);
+            });
+            Arc::new(policy)
+        });
+
+        let audit_log = match config.audit {
+            Some(ref audit) => AuditLog::new(&audit.path),
+            None => AuditLog::new(DEFAULT_AUDIT_PATH),
+        };
+
         Handler {
             config,
+            server,
             http,
             thread_stdin: stdin,
+            process: Arc::new(Mutex::new(None)),
             log_thread: Arc::new(Mutex::new(None)),
+            server_exit: Arc::new(Notify::new()),
+            game_saved: Arc::new(Notify::new()),
+            backup_lock: Arc::new(Mutex::new(())),
+            pending_restore: Arc::new(Mutex::new(None)),
+            players: PlayerRegistry::default(),
+            auto_stop: AutoStopState::default(),
+            played_since_backup: Arc::new(AtomicBool::new(false)),
+            backed_up_before_auto_stop: Arc::new(AtomicBool::new(false)),
+            stop_reason: StopReasonRecorder::default(),
+            crash_count: Arc::new(AtomicU32::new(0)),
+            restart_pending: Arc::new(AtomicBool::new(false)),
+            wake_listener: Arc::new(Mutex::new(None)),
+            command_policy,
+            pending_approvals: Arc::new(Mutex::new(HashMap::new())),
+            audit_log,
+            bridge_webhook: Arc::new(Mutex::new(None)),
         }
     }
 
+    pub fn name(&self) -> &str {
+        self.server.name()
+    }
+
+    fn channel_id(&self) -> ChannelId {
+        self.server
+            .channel_id
+            .unwrap_or(self.config.permission.channel_id)
+    }
+
     async fn send_message(&self, message: impl AsRef<str>) -> Result<Message, SerenityError> {
-        let channel = self.config.permission.channel_id;
+        let channel = self.channel_id();
         channel.say(&self.http, message.as_ref()).await
     }
 
     async fn is_server_running(&self) -> bool {
-        self.thread_stdin.lock().await.is_some()
+        self.process.lock().await.is_some()
     }
 
-    #[inline]
-    fn is_allowed_user(&self, id: UserId) -> bool {
-        self.config.permission.user_id.contains(&id)
+    /// Check whether the user may run the command.
+    ///
+    /// Returns `None` if the user has no permission at all, so that the bot can ignore them.
+    fn check_permission(&self, user: UserId, roles: &[RoleId], command: &str) -> Option<bool> {
+        let permission = &self.config.permission;
+        let level = permission.level_of(user, roles)?;
+        Some(level >= permission.required_level(command))
     }
 
     #[inline]
     fn is_allowed_channel(&self, id: ChannelId) -> bool {
-        id == self.config.permission.channel_id
+        id == self.channel_id()
     }
-}
 
-#[serenity::async_trait]
-impl EventHandler for Handler {
-    async fn message(&self, _: Context, msg: Message) {
-        if !self.is_allowed_user(msg.author.id) || !self.is_allowed_channel(msg.channel_id) {
-            return;
+    /// Check the permission of the command and record the denied one to the audit log.
+    ///
+    /// Returns whether the command may be run.
+    async fn authorize(&self, user: &User, roles: &[RoleId], command: &str, args: &[&str]) -> bool {
+        match self.check_permission(user.id, roles, command) {
+            Some(true) => {}
+            Some(false) => {
+                self.audit(user, command, args, AuditResult::Denied);
+                self.send_message("このコマンドを実行する権限がありません。")
+                    .await
+                    .ok();
+                return false;
+            }
+            None => return false,
         }
 
-        let Some(args) = parse_command(&msg.content) else {
-            return;
-        };
-        let command = args[0];
-        let args = args[1..].to_vec();
+        true
+    }
+
+    /// Run the command and return the result to record to the audit log.
+    async fn run_command(&self, command: &str, args: Vec<&str>, user: UserId) -> AuditResult {
+        let result = self.audit_result(command, &args);
 
-        match command {
+        let done = match command {
             // サーバ開始
             "mcstart" => self.mcstart().await,
             // コマンド送信
-            "mcc" => send_command_to_server(self, args).await,
+            "mcc" => send_command_to_server(self, args, user).await,
             // サーバ停止
             "mcend" => send_stop_to_server(self).await,
-            // クライアント停止
-            "mcsvend" => mcsvend(self).await,
+            // サーバの強制終了
+            "mckill" => self.mckill().await,
+            // サーバの再起動
+            "mcrestart" => self.mcrestart(args).await,
+            // サーバの状態
+            "mcstatus" => {
+                self.mcstatus().await;
+                true
+            }
+            // オンラインのプレイヤー
+            "mcplayers" => {
+                self.mcplayers().await;
+                true
+            }
+            // 自動停止の一時停止と再開
+            "mcautostop" => self.mcautostop(args).await,
             // バックアップ
-            "mcbackup" => save_backup(),
+            "mcbackup" => self.mcbackup(args).await,
+            // バックアップから復元
+            "mcrestore" => self.mcrestore(args, user).await,
+            // 監査ログ
+            "mcaudit" => {
+                self.mcaudit(args).await;
+                true
+            }
             _ => {
                 self.send_message("存在しないコマンドです。").await.ok();
+                false
             }
-        }
-    }
-
-    async fn ready(&self, ctx: Context, ready: Ready) {
-        let Ok(channel) = ctx
-            .http
-            .get_channel(self.config.permission.channel_id)
-            .await
-        else {
-            println!("設定で指定されているチャンネルが見つかりません。permission.channel_id の値を修正してください。");
-            println!("* BOTがチャンネルのあるサーバに参加しているか確認してください。");
-            exit(-1);
         };
 
-        let Some(channel) = channel.guild() else {
-            println!("プライベートチャンネル、チャンネルカテゴリーを管理用チャンネルに指定することはできません。permission.channel_id の値を修正してください。");
-            exit(-1);
-        };
-
-        // テキストチャンネルであることを確認
-        if ChannelType::Text != channel.kind {
-            println!("ボイスチャンネルやスレッド、フォーラムなどを管理用チャンネルに指定することはできません。テキストチャンネルを指定してください。");
-            exit(-1);
+        if done {
+            result
+        } else {
+            AuditResult::Failed
         }
-
-        println!("Discordに接続しました。");
-        println!("BOTの名前: {}", ready.user.tag());
-        println!(
-            "管理チャンネル: {} (in {})",
-            channel.name(),
-            channel
-                .guild_id
-                .to_partial_guild(ctx.http)
-                .await
-                .unwrap()
-                .name
-        );
     }
 }
@@ -0,0 +1,125 @@
+//! Approval of console commands by a second user.
+use super::command_policy::PolicyDecision;
+use super::*;
+use crate::audit::AuditResult;
+use serenity::all::{Reaction, ReactionType};
+use std::time::{Duration, Instant};
+
+/// 承認に使うリアクション
+const APPROVE_EMOJI: &str = "✅";
+
+/// `approval_timeout` が設定されていない場合に承認を待つ時間
+const DEFAULT_APPROVAL_TIMEOUT: Duration = Duration::from_secs(300);
+
+/// 承認待ちのコマンド
+pub struct PendingApproval {
+    requester: UserId,
+    command: String,
+    requested_at: Instant,
+}
+
+impl Handler {
+    /// Run a console command requested by the user, following the command policy.
+    ///
+    /// Returns the reply to the user, or `None` if the command is waiting for approval.
+    pub async fn request_console_command(&self, user: UserId, command: String) -> Option<String> {
+        match self.console_decision(&command) {
+            PolicyDecision::Allow => Some(self.run_console_command(command).await),
+            PolicyDecision::Deny => Some(format!(
+                "`{command}` は実行が許可されていないコマンドです。"
+            )),
+            PolicyDecision::NeedsApproval => match self.request_approval(user, command).await {
+                Ok(_) => None,
+                Err(err) => Some(format!("承認の依頼を送信できませんでした。\n{err}")),
+            },
+        }
+    }
+
+    pub(super) fn console_decision(&self, command: &str) -> PolicyDecision {
+        match self.command_policy {
+            Some(ref policy) => policy.check(command),
+            None => PolicyDecision::Allow,
+        }
+    }
+
+    async fn request_approval(&self, user: UserId, command: String) -> Result<(), SerenityError> {
+        let timeout = self.approval_timeout();
+        let message = self
+            .send_message(format!(
+                "<@{user}> が `{command}` の実行を求めています。\n他のユーザが {APPROVE_EMOJI} でリアクションすると実行されます。({}秒以内)",
+                timeout.as_secs()
+            ))
+            .await?;
+        message
+            .react(&self.http, ReactionType::Unicode(APPROVE_EMOJI.to_string()))
+            .await
+            .ok();
+
+        let mut pending = self.pending_approvals.lock().await;
+        pending.retain(|_, p| p.requested_at.elapsed() < timeout);
+        pending.insert(
+            message.id,
+            PendingApproval {
+                requester: user,
+                command,
+                requested_at: Instant::now(),
+            },
+        );
+
+        Ok(())
+    }
+
+    pub(super) async fn approve(&self, reaction: Reaction) {
+        if !self.is_allowed_channel(reaction.channel_id)
+            || reaction.emoji != ReactionType::Unicode(APPROVE_EMOJI.to_string())
+        {
+            return;
+        }
+        // BOT自身のリアクションは無視する
+        let Some(member) = reaction.member.as_ref().filter(|m| !m.user.bot) else {
+            return;
+        };
+
+        let approval = {
+            let mut pending = self.pending_approvals.lock().await;
+            let Some(approval) = pending.get(&reaction.message_id) else {
+                return;
+            };
+            if approval.requester == member.user.id
+                || self.check_permission(member.user.id, &member.roles, "mcc") != Some(true)
+            {
+                return;
+            }
+
+            pending.remove(&reaction.message_id).unwrap()
+        };
+
+        if approval.requested_at.elapsed() >= self.approval_timeout() {
+            self.send_message(format!(
+                "`{}` の承認期限が過ぎています。もう一度実行してください。",
+                approval.command
+            ))
+            .await
+            .ok();
+            return;
+        }
+
+        let args: Vec<&str> = approval.command.split(' ').collect();
+        self.audit(&member.user, "mcc", &args, AuditResult::Approved);
+
+        let output = self.run_console_command(approval.command).await;
+        self.send_message(format!("<@{}> が承認しました。\n{output}", member.user.id))
+            .await
+            .ok();
+    }
+
+    fn approval_timeout(&self) -> Duration {
+        self.config
+            .permission
+            .console
+            .as_ref()
+            .and_then(|c| c.approval_timeout)
+            .map(Duration::from_secs)
+            .unwrap_or(DEFAULT_APPROVAL_TIMEOUT)
+    }
+}
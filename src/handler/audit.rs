@@ -0,0 +1,105 @@
+use super::command_policy::PolicyDecision;
+use super::log_sender::{split_message, CODEBLOCK_LENGTH, DISCORD_MESSAGE_LENGTH_LIMIT};
+use super::Handler;
+use crate::audit::{AuditEntry, AuditResult};
+use serenity::all::User;
+
+/// `!mcaudit` で表示する件数の既定値
+const DEFAULT_COUNT: usize = 10;
+
+/// `!mcaudit` で表示する最大件数
+const MAX_COUNT: usize = 50;
+
+impl Handler {
+    /// Record an action to the audit log.
+    pub(super) fn audit(&self, user: &User, command: &str, args: &[&str], result: AuditResult) {
+        let entry = AuditEntry {
+            timestamp: chrono::Local::now().to_rfc3339(),
+            user_id: user.id.get(),
+            user_tag: user.tag(),
+            server: self.name().to_string(),
+            command: command.to_string(),
+            args: args.iter().map(|arg| arg.to_string()).collect(),
+            result,
+        };
+
+        if let Err(err) = self.audit_log.append(&entry) {
+            println!("監査ログを書き込めませんでした: {err}");
+        }
+    }
+
+    /// Get the result of the command decided by the permission and the console policy.
+    pub(super) fn audit_result(&self, command: &str, args: &[&str]) -> AuditResult {
+        if command != "mcc" {
+            return AuditResult::Accepted;
+        }
+
+        match self.console_decision(&args.join(" ")) {
+            PolicyDecision::Allow => AuditResult::Accepted,
+            PolicyDecision::NeedsApproval => AuditResult::PendingApproval,
+            PolicyDecision::Deny => AuditResult::Rejected,
+        }
+    }
+
+    /// 監査ログの最新のエントリを表示します。
+    pub async fn mcaudit(&self, args: Vec<&str>) {
+        let count = match args.first() {
+            None => DEFAULT_COUNT,
+            Some(n) => match n.parse::<usize>() {
+                Ok(n) if (1..=MAX_COUNT).contains(&n) => n,
+                _ => {
+                    self.send_message(format!(
+                        "件数には1から{MAX_COUNT}までの数値を指定してください。"
+                    ))
+                    .await
+                    .ok();
+                    return;
+                }
+            },
+        };
+
+        let log = self.audit_log.clone();
+        let entries = tokio::task::spawn_blocking(move || log.latest(count))
+            .await
+            .unwrap_or_else(|err| Err(std::io::Error::other(err)));
+        let entries = match entries {
+            Ok(entries) => entries,
+            Err(err) => {
+                self.send_message(format!("監査ログを読み込めませんでした。\n{err}"))
+                    .await
+                    .ok();
+                return;
+            }
+        };
+
+        if entries.is_empty() {
+            self.send_message("監査ログはありません。").await.ok();
+            return;
+        }
+
+        let mut lines = String::new();
+        for entry in entries {
+            let time = chrono::DateTime::parse_from_rfc3339(&entry.timestamp)
+                .map(|t| t.format("%Y/%m/%d %H:%M:%S").to_string())
+                .unwrap_or(entry.timestamp);
+            let command = std::iter::once(entry.command.as_str())
+                .chain(entry.args.iter().map(String::as_str))
+                .collect::<Vec<_>>()
+                .join(" ");
+            lines.push_str(&format!(
+                "{time}  {}  {}  [{}] {command}\n",
+                entry.user_tag,
+                entry.result.as_str(),
+                entry.server
+            ));
+        }
+
+        // 件数が多いと1つのメッセージに収まらないので、分けて送信する
+        for chunk in split_message(&lines, DISCORD_MESSAGE_LENGTH_LIMIT - CODEBLOCK_LENGTH) {
+            if let Err(err) = self.send_message(format!("```\n{chunk}\n```")).await {
+                println!("監査ログを送信できませんでした: {err}");
+                return;
+            }
+        }
+    }
+}
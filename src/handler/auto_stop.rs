@@ -0,0 +1,135 @@
+use super::Handler;
+use crate::schedule::{format_duration, parse_duration};
+use crate::server::{AutoStopEvent, AutoStopPolicy, AutoStopStatus};
+use std::sync::atomic::Ordering;
+use std::time::Duration;
+
+impl Handler {
+    /// 設定から自動停止の条件を作ります。自動停止しない場合は `None` を返します。
+    pub(super) fn auto_stop_policy(&self) -> Option<AutoStopPolicy> {
+        let auto_stop = self.server.auto_stop()?;
+
+        Some(AutoStopPolicy {
+            idle: Duration::from_secs(auto_stop.idle_minutes * 60),
+            grace: Duration::from_secs(auto_stop.grace_minutes * 60),
+            warning: auto_stop.warning_seconds.map(Duration::from_secs),
+            // 設定の読み込み時に確認している
+            keep_running: auto_stop.keep_running().unwrap_or_default(),
+        })
+    }
+
+    /// `!mcautostop off [期間]` で自動停止を一時停止し、`!mcautostop on` で再開します。
+    ///
+    /// 引数がなければ、自動停止の状態を表示します。
+    pub async fn mcautostop(&self, args: Vec<&str>) -> bool {
+        if self.server.auto_stop().is_none() {
+            self.send_message("自動停止が設定されていません。")
+                .await
+                .ok();
+            return false;
+        }
+
+        // 一時停止は起動中のサーバにだけ適用する
+        if !args.is_empty() && !self.is_server_running().await {
+            self.send_message("起動していません！").await.ok();
+            return false;
+        }
+
+        let (message, done) = match args.as_slice() {
+            [] => (self.auto_stop_status().unwrap_or_default(), true),
+            ["on"] => {
+                self.auto_stop.resume();
+                ("自動停止を再開しました。".to_string(), true)
+            }
+            ["off"] => {
+                self.auto_stop.pause(None);
+                (
+                    "自動停止を一時停止しました。`!mcautostop on` で再開します。".to_string(),
+                    true,
+                )
+            }
+            ["off", duration] => match parse_duration(duration) {
+                Ok(duration) => {
+                    self.auto_stop.pause(Some(duration));
+                    let message = format!(
+                        "自動停止を{}停止しました。`!mcautostop on` で再開します。",
+                        format_duration(duration)
+                    );
+                    (message, true)
+                }
+                Err(err) => (err, false),
+            },
+            _ => (
+                "使い方: `!mcautostop [on | off [期間]]` (例: `!mcautostop off 2h`)".to_string(),
+                false,
+            ),
+        };
+
+        self.send_message(message).await.ok();
+        done
+    }
+
+    /// 自動停止の状態を表示する文字列を返します。自動停止しない場合は `None` を返します。
+    pub(super) fn auto_stop_status(&self) -> Option<String> {
+        self.server.auto_stop()?;
+
+        let status = match self.auto_stop.status() {
+            AutoStopStatus::Waiting => "自動停止: 待機中".to_string(),
+            AutoStopStatus::Countdown(remaining) => {
+                format!("自動停止: あと{}", format_duration(remaining))
+            }
+            AutoStopStatus::Paused(None) => "自動停止: 一時停止中".to_string(),
+            AutoStopStatus::Paused(Some(remaining)) => format!(
+                "自動停止: 一時停止中 (あと{}で再開)",
+                format_duration(remaining)
+            ),
+        };
+        Some(status)
+    }
+
+    /// 自動停止の予告と、停止前の処理を行います。
+    pub(super) async fn on_auto_stop(&self, event: AutoStopEvent) {
+        let Some(auto_stop) = self.server.auto_stop() else {
+            return;
+        };
+
+        match event {
+            AutoStopEvent::Warn(remaining) => {
+                let remaining = format_duration(remaining);
+                self.send_console(&format!(
+                    "say プレイヤーがいないため、サーバは{remaining}後に自動停止します"
+                ))
+                .await;
+                self.send_message(format!(
+                    "プレイヤーがいないため、{remaining}後に自動停止します。"
+                ))
+                .await
+                .ok();
+            }
+            AutoStopEvent::Stopping => {
+                self.send_message(format!(
+                    "プレイヤーが{}分間いないため、自動停止します。",
+                    auto_stop.idle_minutes
+                ))
+                .await
+                .ok();
+
+                let backed_up =
+                    auto_stop.backup && self.server.backup.is_some() && self.run_backup().await;
+                self.backed_up_before_auto_stop
+                    .store(backed_up, Ordering::SeqCst);
+            }
+            AutoStopEvent::Cancelled => {
+                self.send_message("プレイヤーが参加したため、自動停止を中止しました。")
+                    .await
+                    .ok();
+            }
+        }
+    }
+
+    /// 自動停止の前にバックアップを作成できたか。一度確認するとリセットされます。
+    pub(super) fn backed_up_before_auto_stop(&self) -> bool {
+        self.backed_up_before_auto_stop
+            .swap(false, Ordering::SeqCst)
+    }
+}
@@ -0,0 +1,382 @@
+use super::Handler;
+use crate::save::{
+    backup::save_backup,
+    restore::restore_backup,
+    retention::{list_archives, prune_archives},
+};
+use serenity::all::UserId;
+use std::{
+    fs, io,
+    path::PathBuf,
+    sync::atomic::Ordering,
+    time::{Duration, Instant},
+};
+
+/// `save-all flush` の完了を待つ時間
+const SAVE_TIMEOUT: Duration = Duration::from_secs(120);
+
+/// `!mcbackup list` で表示する最大件数
+const LIST_LIMIT: usize = 20;
+
+/// 復元の確認を待つ時間
+const RESTORE_CONFIRM_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// 確認待ちの復元
+pub struct PendingRestore {
+    user: UserId,
+    archive: PathBuf,
+    requested_at: Instant,
+}
+
+impl Handler {
+    pub async fn mcbackup(&self, args: Vec<&str>) -> bool {
+        if self.server.backup.is_none() {
+            self.send_message("バックアップが設定されていません。")
+                .await
+                .ok();
+            return false;
+        }
+
+        match args.first() {
+            None => self.run_backup().await,
+            Some(&"list") => {
+                self.list_backups().await;
+                true
+            }
+            Some(_) => {
+                self.send_message("使い方: `!mcbackup` または `!mcbackup list`")
+                    .await
+                    .ok();
+                false
+            }
+        }
+    }
+
+    /// ワールドのバックアップを作成して、結果をチャンネルに送信します。
+    ///
+    /// サーバが起動している場合は、保存を止めてから圧縮します。
+    /// バックアップを作成できた場合は `true` を返します。
+    pub async fn run_backup(&self) -> bool {
+        let Ok(_backup_lock) = self.backup_lock.try_lock() else {
+            self.send_message("バックアップを実行中です。").await.ok();
+            return false;
+        };
+
+        self.send_message("バックアップを開始します……").await.ok();
+
+        let running = self.is_server_running().await;
+        if running {
+            self.send_message("ワールドを保存しています……").await.ok();
+
+            if let Err(err) = self.flush_world().await {
+                self.send_console("save-on").await;
+                self.send_message(format!("バックアップを中止しました。\n{err}"))
+                    .await
+                    .ok();
+                return false;
+            }
+        }
+
+        self.send_message("圧縮しています……").await.ok();
+        let server = self.server.clone();
+        let res = tokio::task::spawn_blocking(move || save_backup(&server))
+            .await
+            .unwrap_or_else(|err| Err(io::Error::other(err)));
+
+        if running {
+            self.send_console("save-on").await;
+        }
+
+        match res {
+            Ok(path) => {
+                // 今オンラインのプレイヤーがいれば、次回もバックアップの対象にする
+                let online = !self.players.is_empty();
+                self.played_since_backup.store(online, Ordering::SeqCst);
+
+                let size = fs::metadata(&path).map(|m| m.len()).unwrap_or_default();
+                let name = path.file_name().unwrap_or_default().to_string_lossy();
+
+                self.send_message(format!(
+                    "バックアップが完了しました: `{name}` ({})",
+                    format_size(size)
+                ))
+                .await
+                .ok();
+
+                self.prune_backups().await;
+                true
+            }
+            Err(err) => {
+                self.send_message(format!("バックアップに失敗しました。\n{err}"))
+                    .await
+                    .ok();
+                false
+            }
+        }
+    }
+
+    /// 保持設定に従って古いバックアップを削除します。
+    async fn prune_backups(&self) {
+        let Some(ref backup) = self.server.backup else {
+            return;
+        };
+        let Some(retention) = backup.retention.clone() else {
+            return;
+        };
+
+        let output_dir = backup.output_dir.clone();
+        let res = tokio::task::spawn_blocking(move || prune_archives(&output_dir, &retention))
+            .await
+            .unwrap_or_else(|err| Err(io::Error::other(err)));
+
+        match res {
+            Ok(deleted) if deleted.is_empty() => {}
+            Ok(deleted) => {
+                self.send_message(format!(
+                    "古いバックアップを{}個削除しました。",
+                    deleted.len()
+                ))
+                .await
+                .ok();
+            }
+            Err(err) => {
+                self.send_message(format!("古いバックアップを削除できませんでした。\n{err}"))
+                    .await
+                    .ok();
+            }
+        }
+    }
+
+    /// バックアップからワールドを復元します。
+    ///
+    /// `!mcrestore <archive>` で復元を予約し、同じユーザが `!mcrestore confirm` を送信すると実行します。
+    pub async fn mcrestore(&self, args: Vec<&str>, user: UserId) -> bool {
+        let Some(ref backup) = self.server.backup else {
+            self.send_message("バックアップが設定されていません。")
+                .await
+                .ok();
+            return false;
+        };
+
+        match args.first() {
+            None => {
+                self.send_message("使い方: `!mcrestore <バックアップ名>`\nバックアップ名は `!mcbackup list` で確認できます。")
+                    .await
+                    .ok();
+                false
+            }
+            Some(&"confirm") => self.confirm_restore(user).await,
+            Some(name) => {
+                if self.is_server_running().await {
+                    self.send_message(
+                        "サーバが起動しているため、復元できません。`!mcend` で停止してください。",
+                    )
+                    .await
+                    .ok();
+                    return false;
+                }
+
+                // 一覧にあるファイルだけを受け付ける
+                let archive = list_archives(&backup.output_dir)
+                    .ok()
+                    .and_then(|archives| archives.into_iter().find(|a| a.name() == *name));
+                let Some(archive) = archive else {
+                    self.send_message("指定されたバックアップが見つかりません。`!mcbackup list` で確認してください。")
+                        .await
+                        .ok();
+                    return false;
+                };
+
+                self.send_message(format!(
+                    "`{}` ({}) から復元します。上書きされるファイルは退避されます。\n{}秒以内に `!mcrestore confirm` と入力してください。",
+                    archive.name(),
+                    archive.time.format("%Y/%m/%d %H:%M:%S"),
+                    RESTORE_CONFIRM_TIMEOUT.as_secs()
+                ))
+                .await
+                .ok();
+
+                *self.pending_restore.lock().await = Some(PendingRestore {
+                    user,
+                    archive: archive.path,
+                    requested_at: Instant::now(),
+                });
+                true
+            }
+        }
+    }
+
+    /// 予約した復元を実行します。復元できた場合は `true` を返します。
+    async fn confirm_restore(&self, user: UserId) -> bool {
+        let pending = {
+            let mut pending_restore = self.pending_restore.lock().await;
+            match pending_restore.take() {
+                Some(pending) if pending.user == user => Some(pending),
+                other => {
+                    // 他のユーザの予約は取り消さない
+                    *pending_restore = other;
+                    None
+                }
+            }
+        };
+
+        let Some(pending) = pending.filter(|p| p.requested_at.elapsed() <= RESTORE_CONFIRM_TIMEOUT)
+        else {
+            self.send_message("確認待ちの復元はありません。").await.ok();
+            return false;
+        };
+
+        // 起動処理と同時に進まないように、ロックを取ってから起動しているか確認する
+        let Ok(_backup_lock) = self.backup_lock.try_lock() else {
+            self.send_message("バックアップを実行中です。").await.ok();
+            return false;
+        };
+
+        // 確認を待っている間にサーバが起動された場合
+        if self.is_server_running().await {
+            self.send_message(
+                "サーバが起動しているため、復元できません。`!mcend` で停止してください。",
+            )
+            .await
+            .ok();
+            return false;
+        }
+
+        let Some(backup_conf) = self.server.backup.clone() else {
+            return false;
+        };
+
+        self.send_message("復元しています……").await.ok();
+
+        let server_dir = self.server.work_dir.clone();
+        let res = tokio::task::spawn_blocking(move || {
+            restore_backup(&server_dir, &pending.archive, &backup_conf)
+        })
+        .await
+        .unwrap_or_else(|err| Err(io::Error::other(err)));
+
+        match res {
+            Ok(safety_copy) => {
+                let mut message = "復元が完了しました。".to_string();
+                if let Some(safety_copy) = safety_copy {
+                    let name = safety_copy
+                        .file_name()
+                        .unwrap_or_default()
+                        .to_string_lossy();
+                    message.push_str(&format!("\n復元前のファイルは `{name}` に退避しました。"));
+                }
+                self.send_message(message).await.ok();
+                true
+            }
+            Err(err) => {
+                self.send_message(format!(
+                    "復元に失敗しました。ワールドは元に戻されました。\n{err}"
+                ))
+                .await
+                .ok();
+                false
+            }
+        }
+    }
+
+    /// バックアップの一覧を表示します。
+    async fn list_backups(&self) {
+        let Some(ref backup) = self.server.backup else {
+            return;
+        };
+
+        let archives = match list_archives(&backup.output_dir) {
+            Ok(archives) => archives,
+            Err(err) => {
+                self.send_message(format!("バックアップの一覧を取得できませんでした。\n{err}"))
+                    .await
+                    .ok();
+                return;
+            }
+        };
+
+        if archives.is_empty() {
+            self.send_message("バックアップはありません。").await.ok();
+            return;
+        }
+
+        let total: u64 = archives.iter().map(|a| a.size).sum();
+        let mut message = format!(
+            "バックアップ: {}個 (合計 {})\n```\n",
+            archives.len(),
+            format_size(total)
+        );
+        for archive in archives.iter().take(LIST_LIMIT) {
+            message.push_str(&format!(
+                "{}  {:>9}  {}\n",
+                archive.time.format("%Y/%m/%d %H:%M:%S"),
+                format_size(archive.size),
+                archive.name()
+            ));
+        }
+        if archives.len() > LIST_LIMIT {
+            message.push_str(&format!("…ほか{}個\n", archives.len() - LIST_LIMIT));
+        }
+        message.push_str("```");
+
+        self.send_message(message).await.ok();
+    }
+
+    /// 自動保存を止めて、ワールドをディスクに書き出します。
+    async fn flush_world(&self) -> io::Result<()> {
+        // 通知を取りこぼさないように、save-allを送信する前に待機を登録する
+        let saved = self.game_saved.notified();
+
+        self.send_console("save-off").await;
+        if !self.send_console("save-all flush").await {
+            return Err(io::Error::other("サーバにコマンドを送信できませんでした"));
+        }
+
+        tokio::time::timeout(SAVE_TIMEOUT, saved)
+            .await
+            .map_err(|_| {
+                io::Error::new(
+                    io::ErrorKind::TimedOut,
+                    "ワールドの保存が完了しませんでした",
+                )
+            })
+    }
+
+    /// 標準入力からサーバにコマンドを送信します。送信できたかどうかを返します。
+    pub(super) async fn send_console(&self, command: &str) -> bool {
+        let stdin = self.thread_stdin.lock().await;
+        stdin
+            .as_ref()
+            .is_some_and(|stdin| stdin.send(command.to_string()).is_ok())
+    }
+}
+
+/// バイト数を読みやすい単位に変換する
+fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["B", "KB", "MB", "GB", "TB"];
+
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+
+    if unit == 0 {
+        format!("{bytes} B")
+    } else {
+        format!("{size:.1} {}", UNITS[unit])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::format_size;
+
+    #[test]
+    fn format_size_correctly() {
+        assert_eq!(format_size(512), "512 B");
+        assert_eq!(format_size(1536), "1.5 KB");
+        assert_eq!(format_size(5 * 1024 * 1024 * 1024), "5.0 GB");
+    }
+}
@@ -0,0 +1,177 @@
+//! Chat bridge between Minecraft and a Discord channel.
+use super::Handler;
+use crate::server::LogEvent;
+use serenity::all::{
+    ChannelId, CreateAllowedMentions, CreateMessage, ExecuteWebhook, Message, Webhook,
+};
+
+/// ゲーム内に送るメッセージの最大文字数
+const GAME_MESSAGE_LENGTH_LIMIT: usize = 256;
+
+impl Handler {
+    pub(super) fn is_bridge_channel(&self, id: ChannelId) -> bool {
+        self.server
+            .bridge
+            .as_ref()
+            .is_some_and(|bridge| bridge.channel_id == id)
+    }
+
+    /// Post an in-game event to the bridge channel.
+    pub(super) async fn bridge_event(&self, event: &LogEvent) {
+        let Some(ref bridge) = self.server.bridge else {
+            return;
+        };
+
+        let content = match event {
+            LogEvent::Chat { name, text } => {
+                // Webhookが設定されていれば、プレイヤーの名前で送信する
+                if bridge.webhook_url.is_some() && self.send_as_player(name, text).await {
+                    return;
+                }
+                format!("**{}**: {}", escape_discord(name), escape_discord(text))
+            }
+            LogEvent::PlayerJoin { name } => {
+                format!("➡️ {} が参加しました", escape_discord(name))
+            }
+            LogEvent::PlayerLeave { name, .. } => {
+                format!("⬅️ {} が退出しました", escape_discord(name))
+            }
+            LogEvent::Death { message, .. } => format!("💀 {}", escape_discord(message)),
+            LogEvent::Advancement { name, advancement } => format!(
+                "🏆 {} が進捗 [{}] を達成しました",
+                escape_discord(name),
+                escape_discord(advancement)
+            ),
+            LogEvent::PlayerUuid { .. }
+            | LogEvent::PlayerLogin { .. }
+            | LogEvent::PlayerList { .. }
+            | LogEvent::Warn(_)
+            | LogEvent::Error(_) => return,
+        };
+
+        let message = CreateMessage::new()
+            .content(content)
+            .allowed_mentions(CreateAllowedMentions::new());
+        bridge
+            .channel_id
+            .send_message(&self.http, message)
+            .await
+            .ok();
+    }
+
+    async fn send_as_player(&self, name: &str, text: &str) -> bool {
+        let Some(url) = self
+            .server
+            .bridge
+            .as_ref()
+            .and_then(|b| b.webhook_url.as_ref())
+        else {
+            return false;
+        };
+
+        let mut webhook = self.bridge_webhook.lock().await;
+        if webhook.is_none() {
+            match Webhook::from_url(&self.http, url).await {
+                Ok(v) => *webhook = Some(v),
+                Err(err) => {
+                    println!("Webhookを取得できませんでした: {err}");
+                    return false;
+                }
+            }
+        }
+        let Some(ref webhook) = *webhook else {
+            return false;
+        };
+
+        let message = ExecuteWebhook::new()
+            .username(name)
+            .content(escape_discord(text))
+            .allowed_mentions(CreateAllowedMentions::new());
+        webhook.execute(&self.http, false, message).await.is_ok()
+    }
+
+    /// Relay a message in the bridge channel into the game with `tellraw`.
+    pub(super) async fn relay_to_game(&self, msg: &Message) {
+        // BOTやWebhookのメッセージを送り返さない
+        if msg.author.bot || msg.webhook_id.is_some() {
+            return;
+        }
+
+        let mut text = msg.content.clone();
+        for user in &msg.mentions {
+            text = text
+                .replace(&format!("<@{}>", user.id), &format!("@{}", user.name))
+                .replace(&format!("<@!{}>", user.id), &format!("@{}", user.name));
+        }
+        if !msg.attachments.is_empty() {
+            text.push_str(" [添付ファイル]");
+        }
+        if text.trim().is_empty() {
+            return;
+        }
+
+        let name = msg.author.global_name.as_ref().unwrap_or(&msg.author.name);
+        self.send_console(&tellraw_command(name, &text)).await;
+    }
+}
+
+/// Escape markdown and mentions so that the text is shown as is in Discord.
+fn escape_discord(s: &str) -> String {
+    let mut result = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '\\' | '*' | '_' | '~' | '`' | '|' | '>' | '<' | '#' | '[' | ']' => {
+                result.push('\\');
+                result.push(c);
+            }
+            // メンションにならないように、ゼロ幅スペースを挟む
+            '@' => result.push_str("@\u{200B}"),
+            _ => result.push(c),
+        }
+    }
+    result
+}
+
+/// Build a `tellraw` command which shows a message from Discord to all players.
+fn tellraw_command(name: &str, text: &str) -> String {
+    // 書式コードと改行を取り除く。改行はコマンドの区切りになってしまう
+    let clean = |s: &str| -> String {
+        s.chars()
+            .filter(|c| *c != '§')
+            .map(|c| if c.is_control() { ' ' } else { c })
+            .collect()
+    };
+    let text: String = clean(text)
+        .chars()
+        .take(GAME_MESSAGE_LENGTH_LIMIT)
+        .collect();
+
+    let message = serde_json::json!([
+        "",
+        { "text": "[Discord] ", "color": "blue" },
+        { "text": format!("<{}> ", clean(name)) },
+        { "text": text },
+    ]);
+    format!("tellraw @a {message}")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn escape_markdown_and_mentions() {
+        assert_eq!(escape_discord("**bold** _it_"), "\\*\\*bold\\*\\* \\_it\\_");
+        assert_eq!(escape_discord("@everyone"), "@\u{200B}everyone");
+        assert_eq!(escape_discord("<@123>"), "\\<@\u{200B}123\\>");
+        assert_eq!(escape_discord("こんにちは"), "こんにちは");
+    }
+
+    #[test]
+    fn build_tellraw_command() {
+        assert_eq!(
+            tellraw_command("Alex", "hello \"world\"\nsay §cstop"),
+            r#"tellraw @a ["",{"color":"blue","text":"[Discord] "},{"text":"<Alex> "},{"text":"hello \"world\" say cstop"}]"#
+        );
+    }
+}
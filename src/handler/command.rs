@@ -1,8 +1,26 @@
 use super::log_sender::LogSessionGuildChannel;
 use super::observer::observe;
 use super::Handler;
-use crate::server::{auto_stop_inspect, ServerBuilder};
+use crate::server::{
+    auto_stop_inspect, kill_process, query_status, strip_formatting_codes, terminate_process,
+    RconClient, ServerBuilder,
+};
+use crate::types::{ServerProcess, StopReason};
+use serenity::all::UserId;
+use std::io;
+use std::sync::atomic::Ordering;
 use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::futures::Notified;
+
+/// `stop` を送信してから、Minecraftサーバの終了を待つ時間の既定値 (秒)
+const DEFAULT_STOP_TIMEOUT: u64 = 60;
+
+/// 終了を要求してから、強制終了するまでの猶予
+const KILL_GRACE_PERIOD: Duration = Duration::from_secs(10);
+
+/// Discordに送信するコマンド出力の最大文字数
+const COMMAND_OUTPUT_LENGTH_LIMIT: usize = 1900;
 
 pub fn parse_command(message: &str) -> Option<Vec<&str>> {
     if message.len() <= 1 || !message.starts_with('!') {
@@ -15,13 +33,31 @@ pub fn parse_command(message: &str) -> Option<Vec<&str>> {
 }
 
 impl Handler {
-    pub async fn mcstart(&self) {
-        // 標準入力が存在するなら, 既に起動しているのでreturnする
+    pub async fn mcstart(&self) -> bool {
+        // 手動で起動したら、クラッシュの回数をリセットする
+        self.crash_count.store(0, Ordering::SeqCst);
+        self.start_server().await
+    }
+
+    /// Minecraftサーバを起動して、ログの監視を開始します。
+    ///
+    /// 起動できた場合は `true` を返します。
+    pub async fn start_server(&self) -> bool {
+        // プロセスが存在するなら, 既に起動しているのでreturnする
         if self.is_server_running().await {
             self.send_message("すでに起動しています！").await.ok();
-            return;
+            return false;
         }
 
+        // 復元中のワールドでサーバを起動しない。
+        // 起動するまで復元が始まらないように、プロセスを記録するまでロックを保持する
+        let Ok(backup_lock) = self.backup_lock.try_lock() else {
+            self.send_message("バックアップまたは復元の実行中です。完了してから起動してください。")
+                .await
+                .ok();
+            return false;
+        };
+
         // Create a thread to output server logs
         {
             let start_msg = self.send_message("開始しています……").await.unwrap();
@@ -33,17 +69,33 @@ impl Handler {
 
         // FIXME: Windows限定機能の整理
         #[cfg(target_os = "windows")]
-        let port = self.config.server.port;
+        let port = self.server.port;
         #[cfg(target_os = "windows")]
         crate::server::open_port(port);
 
-        let channel = self.config.permission.channel_id;
+        let channel = self.channel_id();
+
+        // JVMがポートを使えるように、接続の待ち受けをやめる。
+        // 起動するまで待ち受けを再開しないように、ロックを保持する
+        let mut wake_listener = self.wake_listener.lock().await;
+        if let Some(listener) = wake_listener.take() {
+            tokio::task::spawn_blocking(move || listener.stop())
+                .await
+                .ok();
+        }
 
         // Minecraft サーバスレッド
         let Ok(server) = ServerBuilder::new()
-            .jar_file(self.config.server.jar_file.clone())
-            .work_dir(self.config.server.work_dir.clone())
-            .memory(&self.config.server.memory)
+            .java_path(self.server.java_path.clone())
+            .jar_file(self.server.jar_file.clone())
+            .argfiles(self.server.argfiles.clone())
+            .launch_script(self.server.launch_script.clone())
+            .work_dir(self.server.work_dir.clone())
+            .min_memory(self.server.min_memory())
+            .max_memory(self.server.max_memory())
+            .jvm_args(self.server.jvm_args.clone())
+            .server_args(self.server.server_args.clone())
+            .envs(self.server.env.clone())
             .build()
         else {
             channel
@@ -53,9 +105,19 @@ impl Handler {
                 )
                 .await
                 .ok();
-            return;
+            drop(wake_listener);
+            drop(backup_lock);
+            self.start_wake_listener().await;
+            return false;
         };
 
+        self.stop_reason.clear();
+        // 前回の起動で一時停止した自動停止を引き継がない
+        self.auto_stop.reset();
+        *self.process.lock().await = Some(ServerProcess::new(server.id()));
+        drop(wake_listener);
+        drop(backup_lock);
+
         // サーバログを表示して、別スレッドに送信する
         let srv_msg_rx = server.logs();
 
@@ -65,86 +127,287 @@ impl Handler {
         *stdin = Some(command_sender.clone());
 
         // 自動停止システムを起動
-        let player_notifier = if self.config.server.auto_stop {
-            Some(auto_stop_inspect(command_sender, 180))
-        } else {
-            None
-        };
+        let player_notifier = self.auto_stop_policy().map(|policy| {
+            let handler = self.clone();
+            auto_stop_inspect(
+                command_sender,
+                policy,
+                self.stop_reason.clone(),
+                self.players.clone(),
+                self.auto_stop.clone(),
+                move |event| {
+                    let rt = tokio::runtime::Builder::new_current_thread()
+                        .enable_all()
+                        .build()
+                        .unwrap();
+                    rt.block_on(handler.on_auto_stop(event));
+                },
+            )
+        });
 
-        let http = Arc::clone(&self.http);
-        let stdin = Arc::clone(&self.thread_stdin);
-        let log_thread = Arc::clone(&self.log_thread);
-        observe(
-            srv_msg_rx,
-            http,
-            stdin,
-            channel,
-            log_thread,
-            player_notifier,
-        )
+        observe(self.clone(), server, srv_msg_rx, player_notifier);
+        true
     }
-}
 
-/// Discordで送信されたコマンドをMinecraftサーバに送信します。
-pub async fn send_command_to_server(handler: &Handler, args: Vec<&str>) {
-    if args.is_empty() {
-        handler.send_message("引数を入力して下さい！").await.ok();
-        return;
+    /// `stop` を送信してから終了を待つ時間
+    pub(super) fn stop_timeout(&self) -> Duration {
+        Duration::from_secs(self.server.stop_timeout.unwrap_or(DEFAULT_STOP_TIMEOUT))
     }
 
-    let mut stdin = handler.thread_stdin.lock().await;
+    /// 起動中のサーバのプロセスIDを返します。
+    pub(super) async fn server_pid(&self) -> Option<u32> {
+        self.process.lock().await.as_ref().map(|p| p.pid)
+    }
 
-    if let Some(stdin) = stdin.as_mut() {
-        let res = stdin.send(args.join(" "));
-        match res {
-            Ok(_) => {
-                handler.send_message("コマンドを送信しました").await.ok();
-            }
-            Err(err) => {
-                handler
-                    .send_message(format!("コマンドを送信できませんでした。\n{}", err))
-                    .await
-                    .ok();
+    /// 起動中のMinecraftサーバに`stop`を送信し、終了するまで待機します。
+    ///
+    /// `stop_timeout` を過ぎても終了しない場合は強制終了します。
+    pub async fn shutdown_server(&self) {
+        // 通知を取りこぼさないように、stopを送信する前に待機を登録する
+        let exited = self.server_exit.notified();
+
+        {
+            let stdin = self.thread_stdin.lock().await;
+            let Some(stdin) = stdin.as_ref() else {
+                return;
+            };
+
+            self.stop_reason.record(StopReason::Shutdown);
+            if stdin.send("stop".to_string()).is_err() {
+                return;
             }
+        }
+
+        println!("Minecraftサーバの終了を待っています……");
+        self.send_message("サーバを終了しています……").await.ok();
+
+        let exited = match tokio::time::timeout(self.stop_timeout(), exited).await {
+            Ok(_) => return,
+            Err(_) => self.server_exit.notified(),
+        };
+
+        println!("Minecraftサーバが時間内に終了しなかったため、強制終了します。");
+        let Some(pid) = self.server_pid().await else {
+            return;
         };
-    } else {
-        handler.send_message("起動していません！").await.ok();
+        if let Err(err) = self.kill_server(pid, exited).await {
+            println!("Minecraftサーバを強制終了できませんでした: {err}");
+        }
+    }
+
+    /// 応答しないMinecraftサーバを強制終了します。
+    pub async fn mckill(&self) -> bool {
+        // 通知を取りこぼさないように、終了を要求する前に待機を登録する
+        let exited = self.server_exit.notified();
+
+        let Some(pid) = self.server_pid().await else {
+            self.send_message("起動していません！").await.ok();
+            return false;
+        };
+
+        self.send_message("サーバを強制終了しています……").await.ok();
+        if let Err(err) = self.kill_server(pid, exited).await {
+            self.send_message(format!("強制終了できませんでした。\n{err}"))
+                .await
+                .ok();
+            return false;
+        }
+        true
+    }
+
+    /// プロセスに終了を要求し、猶予を過ぎても終了しなければ強制終了します。
+    ///
+    /// プロセスが終了するまで待機します。
+    pub(super) async fn kill_server(&self, pid: u32, exited: Notified<'_>) -> io::Result<()> {
+        self.stop_reason.overwrite(StopReason::Kill);
+        tokio::pin!(exited);
+
+        // Windowsではコンソールのプロセスを穏やかに終了できないことが多いので、失敗しても強制終了に進む
+        if let Err(err) = terminate_process(pid) {
+            println!("サーバのプロセスに終了を要求できませんでした: {err}");
+        } else if tokio::time::timeout(KILL_GRACE_PERIOD, exited.as_mut())
+            .await
+            .is_ok()
+        {
+            return Ok(());
+        }
+
+        kill_process(pid)?;
+        tokio::time::timeout(KILL_GRACE_PERIOD, exited)
+            .await
+            .map_err(|_| io::Error::new(io::ErrorKind::TimedOut, "プロセスが終了しませんでした"))
+    }
+
+    /// RCONでコマンドを実行して、その出力を返します。
+    ///
+    /// RCONが設定されていない場合は `None` を返します。
+    pub async fn send_rcon_command(&self, command: String) -> Option<io::Result<String>> {
+        let rcon = self.server.rcon.clone()?;
+
+        let res = tokio::task::spawn_blocking(move || {
+            let mut client = RconClient::connect(rcon.address(), &rcon.password)?;
+            client.command(&command)
+        })
+        .await
+        .unwrap_or_else(|err| Err(io::Error::other(err)));
+
+        Some(res.map(|output| strip_formatting_codes(&output)))
     }
-}
 
-pub async fn send_stop_to_server(handler: &Handler) {
-    let mut stdin = handler.thread_stdin.lock().await;
+    /// Minecraftサーバでコマンドを実行して、返信するメッセージを返します。
+    pub async fn run_console_command(&self, command: String) -> String {
+        // `!mcc stop` による停止はクラッシュとして扱わない
+        if command.split(' ').next() == Some("stop") && self.is_server_running().await {
+            self.stop_reason.record(StopReason::Command);
+        }
 
-    if let Some(stdin) = stdin.as_mut() {
-        let res = stdin.send("stop".to_string());
-        match res {
-            Ok(_) => {
-                println!("stopping...");
-                handler.send_message("終了しています……").await.ok();
+        // RCONが設定されていれば、RCONで実行して結果を返す。
+        // 起動直後などでRCONに接続できない場合は標準入力から送信する
+        let mut rcon_res = self.send_rcon_command(command.clone()).await;
+        if let Some(Err(ref err)) = rcon_res {
+            if err.kind() == io::ErrorKind::ConnectionRefused && self.is_server_running().await {
+                rcon_res = None;
             }
-            Err(err) => {
-                handler
-                    .send_message(format!(
-                        "終了できませんでした。honeを再起動する必要があります。\n{}",
-                        err
-                    ))
-                    .await
-                    .ok();
+        }
+        if let Some(res) = rcon_res {
+            return match res {
+                Ok(output) if output.is_empty() => "コマンドを実行しました (出力なし)".to_string(),
+                Ok(output) => {
+                    let output: String = output.chars().take(COMMAND_OUTPUT_LENGTH_LIMIT).collect();
+                    format!("```\n{output}\n```")
+                }
+                Err(err) => format!("コマンドを送信できませんでした。\n{}", err),
+            };
+        }
+
+        let stdin = self.thread_stdin.lock().await;
+        let Some(stdin) = stdin.as_ref() else {
+            return "起動していません！".to_string();
+        };
+
+        match stdin.send(command) {
+            Ok(_) => "コマンドを送信しました".to_string(),
+            Err(err) => format!("コマンドを送信できませんでした。\n{}", err),
+        }
+    }
+
+    /// Server List Pingでサーバの状態を取得して表示します。
+    pub async fn mcstatus(&self) {
+        let message = self.status_message().await;
+        self.send_message(message).await.ok();
+    }
+
+    /// Server List Pingでサーバの状態を取得して、表示するメッセージを返します。
+    pub async fn status_message(&self) -> String {
+        let port = self.server.port;
+        let res = tokio::task::spawn_blocking(move || query_status("127.0.0.1", port))
+            .await
+            .unwrap_or_else(|err| Err(io::Error::other(err)));
+
+        let mut message = match res {
+            Ok(status) => {
+                let mut message = format!(
+                    "🟢 稼働中\nMOTD: {}\nバージョン: {}\nプレイヤー: {}/{}",
+                    status.motd(),
+                    status.version.name,
+                    status.players.online,
+                    status.players.max
+                );
+                if !status.players.sample.is_empty() {
+                    let names: Vec<&str> = status
+                        .players
+                        .sample
+                        .iter()
+                        .map(|p| p.name.as_str())
+                        .collect();
+                    message.push_str(&format!(" (`{}`)", names.join(", ")));
+                }
+                message
             }
+            // プロセスは起動しているが、まだ応答しない
+            Err(_) if self.is_server_running().await => "🟡 起動処理中です".to_string(),
+            Err(_) => "🔴 停止中".to_string(),
         };
-    } else {
-        handler.send_message("起動していません！").await.ok();
+
+        if self.is_server_running().await {
+            if let Some(auto_stop) = self.auto_stop_status() {
+                message.push('\n');
+                message.push_str(&auto_stop);
+            }
+        }
+
+        message
     }
+}
 
-    *stdin = None;
+/// Discordで送信されたコマンドをMinecraftサーバに送信します。
+pub async fn send_command_to_server(handler: &Handler, args: Vec<&str>, user: UserId) -> bool {
+    if args.is_empty() {
+        handler.send_message("引数を入力して下さい！").await.ok();
+        return false;
+    }
+
+    if let Some(reply) = handler.request_console_command(user, args.join(" ")).await {
+        handler.send_message(reply).await.ok();
+    }
+    true
 }
 
-pub async fn mcsvend(handler: &Handler) {
-    handler
-        .send_message("クライアントを終了しました。")
-        .await
-        .ok();
-    std::process::exit(0);
+/// 起動中のMinecraftサーバに`stop`を送信します。送信できた場合は `true` を返します。
+pub async fn send_stop_to_server(handler: &Handler) -> bool {
+    let pid = {
+        let mut process = handler.process.lock().await;
+        let Some(process) = process.as_mut() else {
+            handler.send_message("起動していません！").await.ok();
+            return false;
+        };
+
+        if process.stop_requested_at.is_some() {
+            handler
+                .send_message("終了処理中です。`!mckill` で強制終了できます。")
+                .await
+                .ok();
+            return false;
+        }
+
+        process.stop_requested_at = Some(Instant::now());
+        process.pid
+    };
+
+    handler.stop_reason.record(StopReason::Command);
+    if !handler.send_console("stop").await {
+        // 停止していないので、`!mcend` を受け付けるようにする
+        if let Some(process) = handler.process.lock().await.as_mut() {
+            process.stop_requested_at = None;
+        }
+        handler
+            .send_message("終了できませんでした。`!mckill` で強制終了できます。")
+            .await
+            .ok();
+        return false;
+    }
+
+    println!("stopping...");
+    handler.send_message("終了しています……").await.ok();
+
+    // 時間内に終了しなければ、強制終了できることを知らせる
+    let handler = handler.clone();
+    tokio::spawn(async move {
+        let timeout = handler.stop_timeout();
+        tokio::time::sleep(timeout).await;
+
+        if handler.server_pid().await == Some(pid) {
+            handler
+                .send_message(format!(
+                    "サーバが{}秒以内に終了しませんでした。`!mckill` で強制終了できます。",
+                    timeout.as_secs()
+                ))
+                .await
+                .ok();
+        }
+    });
+
+    true
 }
 
 #[cfg(test)]
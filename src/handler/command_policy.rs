@@ -0,0 +1,331 @@
+//! Policy of the console commands sent from Discord.
+use crate::config::ConsoleConfig;
+use glob::Pattern;
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum PolicyDecision {
+    Allow,
+    /// 他のユーザの承認が必要
+    NeedsApproval,
+    Deny,
+}
+
+/// A pattern of console commands.
+///
+/// A pattern without spaces matches the command name, e.g. `op` matches `op Steve`.
+/// Otherwise it matches the whole command, e.g. `time set *`.
+struct CommandPattern {
+    pattern: Pattern,
+    name_only: bool,
+}
+
+impl CommandPattern {
+    fn new(pattern: &str) -> Result<CommandPattern, String> {
+        let pattern = normalize(pattern);
+        let name_only = !pattern.contains(' ');
+        let pattern = Pattern::new(pattern)
+            .map_err(|err| format!("コマンドのパターンが不正です: {pattern}: {err}"))?;
+
+        Ok(CommandPattern { pattern, name_only })
+    }
+
+    fn matches(&self, command: &str) -> bool {
+        let target = if self.name_only {
+            command.split(' ').next().unwrap_or(command)
+        } else {
+            command
+        };
+
+        self.pattern.matches(target)
+    }
+}
+
+pub struct CommandPolicy {
+    allow: Option<Vec<CommandPattern>>,
+    deny: Vec<CommandPattern>,
+    approval: Vec<CommandPattern>,
+}
+
+impl CommandPolicy {
+    pub fn new(config: &ConsoleConfig) -> Result<CommandPolicy, String> {
+        let compile = |patterns: &[String]| {
+            patterns
+                .iter()
+                .map(|p| CommandPattern::new(p))
+                .collect::<Result<Vec<_>, _>>()
+        };
+
+        Ok(CommandPolicy {
+            allow: config.allow.as_deref().map(compile).transpose()?,
+            deny: compile(&config.deny)?,
+            approval: compile(&config.approval)?,
+        })
+    }
+
+    /// Decide whether the command may be run. `deny` takes precedence over `allow`.
+    ///
+    /// The commands run by `execute ... run` and `return run` are checked as well.
+    pub fn check(&self, command: &str) -> PolicyDecision {
+        // 改行を含めると、標準入力で複数のコマンドを実行できてしまう
+        if command.chars().any(char::is_control) {
+            return PolicyDecision::Deny;
+        }
+
+        // 引用符や括弧が閉じていないと、実行されるコマンドを判断できない
+        let Some(commands) = commands(command) else {
+            return PolicyDecision::Deny;
+        };
+        let matches = |patterns: &[CommandPattern], command: &str| {
+            patterns.iter().any(|p| p.matches(command))
+        };
+
+        if commands.iter().any(|c| matches(&self.deny, c)) {
+            return PolicyDecision::Deny;
+        }
+        if let Some(ref allow) = self.allow {
+            if !commands.iter().all(|c| matches(allow, c)) {
+                return PolicyDecision::Deny;
+            }
+        }
+        if commands.iter().any(|c| matches(&self.approval, c)) {
+            return PolicyDecision::NeedsApproval;
+        }
+
+        PolicyDecision::Allow
+    }
+}
+
+/// Remove the leading `/` and the `minecraft:` namespace of the command.
+fn normalize(command: &str) -> &str {
+    let command = command.trim().trim_start_matches('/');
+    command.strip_prefix("minecraft:").unwrap_or(command)
+}
+
+/// The command itself and the commands run by `execute ... run` and `return run`.
+///
+/// Returns `None` if a quote or a bracket of `execute` or `return` is not closed.
+fn commands(command: &str) -> Option<Vec<&str>> {
+    let mut commands = Vec::new();
+    let mut command = normalize(command);
+
+    loop {
+        commands.push(command);
+
+        let run = match command.split(' ').next() {
+            // `execute as @a[name="x run y"] run ...` の括弧内の run は無視する
+            Some("execute") => tokens(command)?
+                .into_iter()
+                .skip(1)
+                .find(|(_, token)| *token == "run"),
+            Some("return") => tokens(command)?
+                .into_iter()
+                .nth(1)
+                .filter(|(_, token)| *token == "run"),
+            _ => None,
+        };
+        match run {
+            Some((offset, _)) => command = normalize(&command[offset + "run".len()..]),
+            None => break,
+        }
+    }
+
+    Some(commands)
+}
+
+/// Split the command at spaces outside quotes and brackets.
+///
+/// Returns the tokens with their byte offsets, or `None` if a quote or a bracket is not closed.
+fn tokens(command: &str) -> Option<Vec<(usize, &str)>> {
+    let mut tokens = Vec::new();
+    let mut start = 0;
+    let mut depth = 0usize;
+    let mut quote = None;
+    let mut escaped = false;
+
+    for (i, c) in command.char_indices() {
+        if let Some(q) = quote {
+            if escaped {
+                escaped = false;
+            } else if c == '\\' {
+                escaped = true;
+            } else if c == q {
+                quote = None;
+            }
+            continue;
+        }
+
+        match c {
+            '"' | '\'' => quote = Some(c),
+            '[' | '{' => depth += 1,
+            ']' | '}' => depth = depth.checked_sub(1)?,
+            ' ' if depth == 0 => {
+                if start < i {
+                    tokens.push((start, &command[start..i]));
+                }
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    if quote.is_some() || depth != 0 {
+        return None;
+    }
+    if start < command.len() {
+        tokens.push((start, &command[start..]));
+    }
+
+    Some(tokens)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy(allow: Option<&[&str]>, deny: &[&str], approval: &[&str]) -> CommandPolicy {
+        let to_vec = |patterns: &[&str]| patterns.iter().map(|p| p.to_string()).collect();
+        CommandPolicy::new(&ConsoleConfig {
+            allow: allow.map(to_vec),
+            deny: to_vec(deny),
+            approval: to_vec(approval),
+            approval_timeout: None,
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn deny_commands() {
+        let policy = policy(None, &["op", "deop", "ban-ip", "stop"], &[]);
+
+        assert_eq!(policy.check("op Steve"), PolicyDecision::Deny);
+        assert_eq!(policy.check("/stop"), PolicyDecision::Deny);
+        assert_eq!(policy.check("say hello"), PolicyDecision::Allow);
+        // コマンド名が一致する場合だけ拒否する
+        assert_eq!(policy.check("opx"), PolicyDecision::Allow);
+    }
+
+    #[test]
+    fn allow_only_listed_commands() {
+        let policy = policy(Some(&["say", "list", "time set *"]), &["say *bad*"], &[]);
+
+        assert_eq!(policy.check("list"), PolicyDecision::Allow);
+        assert_eq!(policy.check("time set day"), PolicyDecision::Allow);
+        assert_eq!(policy.check("time add 100"), PolicyDecision::Deny);
+        assert_eq!(policy.check("give Steve diamond"), PolicyDecision::Deny);
+        assert_eq!(policy.check("say bad words"), PolicyDecision::Deny);
+    }
+
+    #[test]
+    fn require_approval() {
+        let policy = policy(None, &["stop"], &["ban", "whitelist remove *"]);
+
+        assert_eq!(policy.check("ban Steve"), PolicyDecision::NeedsApproval);
+        assert_eq!(
+            policy.check("whitelist remove Steve"),
+            PolicyDecision::NeedsApproval
+        );
+        assert_eq!(policy.check("whitelist add Steve"), PolicyDecision::Allow);
+    }
+
+    #[test]
+    fn deny_multiple_lines() {
+        let policy = policy(None, &["op"], &[]);
+
+        assert_eq!(policy.check("say hi\nop Steve"), PolicyDecision::Deny);
+        assert_eq!(policy.check("say hi\rop Steve"), PolicyDecision::Deny);
+        assert_eq!(policy.check("say hi\u{0}"), PolicyDecision::Deny);
+    }
+
+    #[test]
+    fn deny_namespaced_commands() {
+        let policy = policy(None, &["op"], &[]);
+
+        assert_eq!(policy.check("minecraft:op Steve"), PolicyDecision::Deny);
+        assert_eq!(policy.check("/minecraft:op Steve"), PolicyDecision::Deny);
+    }
+
+    #[test]
+    fn check_commands_run_by_execute() {
+        let policy = policy(None, &["op"], &["ban"]);
+
+        assert_eq!(policy.check("execute run op Steve"), PolicyDecision::Deny);
+        assert_eq!(
+            policy.check("execute as @a at @s run execute run minecraft:op Steve"),
+            PolicyDecision::Deny
+        );
+        assert_eq!(
+            policy.check("execute as Steve run ban Alex"),
+            PolicyDecision::NeedsApproval
+        );
+        assert_eq!(
+            policy.check("execute as @a run say hi"),
+            PolicyDecision::Allow
+        );
+    }
+
+    #[test]
+    fn ignore_run_in_quotes_and_brackets() {
+        let deny = policy(None, &["op"], &[]);
+
+        assert_eq!(
+            deny.check(r#"execute as @a[name=" run say"] run op Steve"#),
+            PolicyDecision::Deny
+        );
+        assert_eq!(
+            deny.check(r#"execute if data storage a {b:' run say'} run op Steve"#),
+            PolicyDecision::Deny
+        );
+        // 閉じていない引用符や括弧は判断できないので拒否する
+        assert_eq!(
+            deny.check(r#"execute as @a[name=" run say hi"#),
+            PolicyDecision::Deny
+        );
+        assert_eq!(
+            deny.check("execute as @a] run say hi"),
+            PolicyDecision::Deny
+        );
+        // execute 以外のコマンドの引用符は確認しない
+        assert_eq!(deny.check("say don't"), PolicyDecision::Allow);
+
+        let allow = policy(Some(&["execute", "say"]), &[], &[]);
+        assert_eq!(
+            allow.check(r#"execute as @a[name=" run say"] run op Steve"#),
+            PolicyDecision::Deny
+        );
+    }
+
+    #[test]
+    fn check_commands_run_by_return() {
+        let policy = policy(None, &["op", "function"], &[]);
+
+        assert_eq!(policy.check("return run op Steve"), PolicyDecision::Deny);
+        assert_eq!(
+            policy.check("execute as @a run return run minecraft:op Steve"),
+            PolicyDecision::Deny
+        );
+        assert_eq!(
+            policy.check("execute run function example:op"),
+            PolicyDecision::Deny
+        );
+        assert_eq!(policy.check("return 1"), PolicyDecision::Allow);
+    }
+
+    #[test]
+    fn allow_execute_only_with_allowed_commands() {
+        // execute と実行するコマンドの両方が許可されている必要がある
+        let policy = policy(Some(&["execute", "say"]), &[], &[]);
+
+        assert_eq!(
+            policy.check("execute as @a run say hi"),
+            PolicyDecision::Allow
+        );
+        assert_eq!(
+            policy.check("execute as @a run give @s diamond"),
+            PolicyDecision::Deny
+        );
+    }
+
+    #[test]
+    fn reject_invalid_pattern() {
+        assert!(CommandPattern::new("say [").is_err());
+    }
+}
@@ -1,7 +1,9 @@
 use std::sync::Arc;
 use std::thread;
 
-use serenity::all::{CreateThread, EditThread, GuildChannel, Message};
+use serenity::all::{
+    CreateAttachment, CreateMessage, CreateThread, EditThread, GuildChannel, Message,
+};
 use serenity::http::Http;
 use serenity::model::prelude::ChannelId;
 use serenity::Result;
@@ -10,7 +12,12 @@ use std::time::Duration;
 
 const MESSAGE_INTERVAL: Duration = Duration::from_millis(800);
 const MESSAGE_NUMBER_THRESHOLD: usize = 10;
-const DISCORD_MESSAGE_LENGTH_LIMIT: usize = 900;
+/// Discordのメッセージの最大文字数
+pub(super) const DISCORD_MESSAGE_LENGTH_LIMIT: usize = 2000;
+/// コードブロックの ```\n と \n``` の文字数
+pub(super) const CODEBLOCK_LENGTH: usize = 8;
+/// これより長い出力は、メッセージに分けずにファイルで送信する
+const ATTACHMENT_THRESHOLD: usize = 4000;
 
 // スレッド名の前につける稼働状況
 const RUNNING_INDICATOR: &str = "[🏃稼働中]";
@@ -94,22 +101,90 @@ impl LogSessionGuildChannel {
         self.channel.edit_thread(&http, edit_thread_builder).await
     }
 
-    async fn internal_say(
-        messages: &[String],
-        http: &Http,
-        thread: ChannelId,
-    ) -> Result<serenity::model::prelude::Message, serenity::Error> {
+    async fn internal_say(messages: &[String], http: &Http, thread: ChannelId) -> Result<()> {
         let messages = messages.concat();
 
-        if messages.len() <= DISCORD_MESSAGE_LENGTH_LIMIT {
-            thread.say(http, Self::wrap_codeblock(&messages)).await
-        } else {
-            let messages = &messages[..DISCORD_MESSAGE_LENGTH_LIMIT];
-            thread.say(http, Self::wrap_codeblock(&format!("{messages}……\n\n出力が長いため、省略されました。Minecraftサーバ側のログを確認してください。"))).await
+        if messages.chars().count() > ATTACHMENT_THRESHOLD {
+            let file_name = format!("{}.log", chrono::Local::now().format("%Y%m%d-%H%M%S"));
+            let message = CreateMessage::new()
+                .content(format!(
+                    "出力が長いため、ファイルで送信します ({}行)",
+                    messages.lines().count()
+                ))
+                .add_file(CreateAttachment::bytes(messages.into_bytes(), file_name));
+            thread.send_message(http, message).await?;
+            return Ok(());
         }
+
+        for chunk in split_message(&messages, DISCORD_MESSAGE_LENGTH_LIMIT - CODEBLOCK_LENGTH) {
+            thread.say(http, Self::wrap_codeblock(&chunk)).await?;
+        }
+        Ok(())
     }
 
     fn wrap_codeblock(str: &str) -> String {
         format!("```\n{str}\n```")
     }
 }
+
+/// Split the text into chunks of at most `limit` characters.
+///
+/// It is split at line breaks if possible, and a line longer than `limit` is split at character boundaries.
+pub(super) fn split_message(text: &str, limit: usize) -> Vec<String> {
+    let mut chunks = Vec::new();
+    let mut chunk = String::new();
+    let mut chunk_len = 0;
+
+    for line in text.split_inclusive('\n') {
+        let line_len = line.chars().count();
+        if chunk_len + line_len > limit && !chunk.is_empty() {
+            chunks.push(std::mem::take(&mut chunk));
+            chunk_len = 0;
+        }
+
+        if line_len <= limit {
+            chunk.push_str(line);
+            chunk_len += line_len;
+            continue;
+        }
+
+        // 1行が長すぎる場合は、文字の境界で分ける
+        let chars: Vec<char> = line.chars().collect();
+        for part in chars.chunks(limit) {
+            chunks.push(part.iter().collect());
+        }
+    }
+    if !chunk.is_empty() {
+        chunks.push(chunk);
+    }
+
+    chunks
+        .into_iter()
+        .map(|chunk| chunk.trim_end_matches('\n').to_string())
+        .filter(|chunk| !chunk.is_empty())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::split_message;
+
+    #[test]
+    fn split_at_line_breaks() {
+        let text = "aaaa\nbbbb\ncccc\n";
+
+        assert_eq!(split_message(text, 10), vec!["aaaa\nbbbb", "cccc"]);
+        assert_eq!(split_message(text, 100), vec!["aaaa\nbbbb\ncccc"]);
+    }
+
+    #[test]
+    fn split_long_line_at_char_boundaries() {
+        // マルチバイト文字の途中で分けない
+        let text = "あいうえおかきくけこ\nさしす";
+
+        assert_eq!(
+            split_message(text, 4),
+            vec!["あいうえ", "おかきく", "けこ", "さしす"]
+        );
+    }
+}
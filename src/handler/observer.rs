@@ -1,47 +1,66 @@
-use super::LogSessionGuildChannel;
-use crate::{server::PlayerNotifier, types::ServerMessage};
-use serenity::{http::Http, model::prelude::ChannelId, prelude::Mutex};
+use super::Handler;
+use crate::{
+    server::{
+        latest_crash_report, parse_player_list, LogEvent, LogLine, PlayerNotifier, PlayerSync,
+        Server,
+    },
+    types::{ServerMessage, StopReason},
+};
+use serenity::all::{CreateAttachment, CreateMessage};
 use std::{
-    sync::mpsc::{Receiver, Sender},
-    sync::Arc,
+    io,
+    process::ExitStatus,
+    sync::atomic::{AtomicU32, Ordering},
+    sync::mpsc::Receiver,
     thread,
+    time::{Duration, SystemTime},
 };
 
+/// `list` でプレイヤーの一覧を取得する間隔
+const PLAYER_SYNC_INTERVAL: Duration = Duration::from_secs(5 * 60);
+
+/// この時間以上稼働してからのクラッシュは、連続したクラッシュとして数えない
+const STABLE_UPTIME: Duration = Duration::from_secs(10 * 60);
+
 /// Observe the server's standard output and send messages to Discord.
 pub fn observe(
+    handler: Handler,
+    mut server: Server,
     srv_msg_rx: Receiver<ServerMessage>,
-    http: Arc<Http>,
-    stdin: Arc<Mutex<Option<Sender<String>>>>,
-    channel: ChannelId,
-    log_thread: Arc<Mutex<Option<LogSessionGuildChannel>>>,
     player_notifier: Option<PlayerNotifier>,
 ) {
+    let started_at = SystemTime::now();
+
     thread::spawn(move || {
         let rt = tokio::runtime::Builder::new_current_thread()
             .enable_all()
             .build()
             .unwrap();
 
+        let http = &handler.http;
+        let channel = handler.channel_id();
+
         rt.block_on(async {
             use ServerMessage::*;
 
+            // 起動が完了してから、プレイヤーの一覧を定期的に取得する
+            let mut player_sync: Option<PlayerSync> = None;
+
             for v in srv_msg_rx {
                 match v {
                     Exit => {
                         println!("サーバが停止しました。");
 
-                        let mut log_thread = log_thread.lock().await;
+                        let mut log_thread = handler.log_thread.lock().await;
 
                         if let Some(ref mut log_thread) = *log_thread {
-                            log_thread.archive(&http).await.ok();
+                            log_thread.archive(http).await.ok();
                         }
-
-                        channel.say(&http, "終了しました").await.ok();
                     }
                     Done => {
                         channel
                             .say(
-                                &http,
+                                http,
                                 "サーバが起動しました！サーバログをスレッドから確認できます。",
                             )
                             .await
@@ -50,25 +69,52 @@ pub fn observe(
                         if let Some(ref player_notifier) = player_notifier {
                             player_notifier.start().unwrap();
                         }
+
+                        if let Some(stdin) = handler.thread_stdin.lock().await.clone() {
+                            player_sync = Some(PlayerSync::start(stdin, PLAYER_SYNC_INTERVAL));
+                        }
                     }
-                    Info(message) => {
+                    Saved => {
+                        // 保存を待っているタスクに通知する
+                        handler.game_saved.notify_waiters();
+                    }
+                    Event(event) => {
+                        handler.bridge_event(&event).await;
+
+                        handler.players.apply(&event);
+                        if let LogEvent::PlayerJoin { .. } = event {
+                            handler.played_since_backup.store(true, Ordering::SeqCst);
+                        }
+
                         if let Some(ref player_notifier) = player_notifier {
-                            if message.contains("joined the game") {
-                                player_notifier.join().ok();
-                            } else if message.contains("left the game") {
-                                player_notifier.leave().ok();
+                            match event {
+                                LogEvent::PlayerJoin { .. } => {
+                                    player_notifier.join().ok();
+                                }
+                                LogEvent::PlayerLeave { .. } => {
+                                    player_notifier.leave().ok();
+                                }
+                                _ => {}
                             }
                         }
+                    }
+                    Info(message) => {
+                        // 定期的に送信した `list` の出力は、スレッドに送らない
+                        let is_list = LogLine::parse(&message)
+                            .is_some_and(|line| parse_player_list(&line.message).is_some());
+                        if is_list && player_sync.as_ref().is_some_and(PlayerSync::take_reply) {
+                            continue;
+                        }
 
                         // スレッドが設定されているなら、スレッドに送信する
-                        let thread_id = log_thread.lock().await;
+                        let thread_id = handler.log_thread.lock().await;
                         if let Some(ref v) = *thread_id {
                             v.say(message).ok();
                         }
                     }
                     Error(e) => {
                         channel
-                            .say(&http, format!("エラーが発生しました:\n```{}\n```", e))
+                            .say(http, format!("エラーが発生しました:\n```{}\n```", e))
                             .await
                             .ok();
                     }
@@ -76,18 +122,174 @@ pub fn observe(
             }
         });
 
+        // プロセスの終了を待って、停止した理由を確定する
+        let status = server.wait();
+        let reason = match handler.stop_reason.take() {
+            StopReason::Crash => StopReason::Crash,
+            StopReason::Kill => StopReason::Kill,
+            // 停止を要求していても、異常な終了コードならクラッシュとして扱う
+            _ if status.as_ref().is_ok_and(|s| !s.success()) => StopReason::Crash,
+            reason => reason,
+        };
+
         // FIXME: Windows限定機能の整理
         #[cfg(target_os = "windows")]
-        {
-            use crate::context::ConfigContext;
+        crate::server::close_port(handler.server.port);
 
-            let config = ConfigContext::get();
-            crate::server::close_port(config.server.port);
+        handler.players.clear();
+
+        {
+            let mut log_thread = handler.log_thread.blocking_lock();
+            *log_thread = None;
+            let mut stdin = handler.thread_stdin.blocking_lock();
+            *stdin = None;
+            let mut process = handler.process.blocking_lock();
+            *process = None;
         }
 
-        let mut log_thread = log_thread.blocking_lock();
-        *log_thread = None;
-        let mut stdin = stdin.blocking_lock();
-        *stdin = None;
+        // 終了を待っているタスクに通知する
+        handler.server_exit.notify_waiters();
+
+        rt.block_on(async {
+            report_stop(&handler, reason, status, started_at).await;
+
+            // 次に接続したプレイヤーのために待ち受ける
+            if reason != StopReason::Shutdown {
+                handler.start_wake_listener().await;
+            }
+        });
     });
 }
+
+/// Report why the server stopped, and restart it if it crashed.
+async fn report_stop(
+    handler: &Handler,
+    reason: StopReason,
+    status: io::Result<ExitStatus>,
+    started_at: SystemTime,
+) {
+    let channel = handler.channel_id();
+
+    match reason {
+        StopReason::Command | StopReason::AutoStop => {
+            let message = match reason {
+                StopReason::AutoStop => "プレイヤーがいないため、自動停止しました",
+                _ => "終了しました",
+            };
+            channel.say(&handler.http, message).await.ok();
+
+            // 停止時のバックアップ。自動停止の前に作成した場合は作成しない
+            let backed_up = reason == StopReason::AutoStop && handler.backed_up_before_auto_stop();
+            let on_stop = !backed_up
+                && handler
+                    .server
+                    .backup
+                    .as_ref()
+                    .and_then(|b| b.schedule.as_ref())
+                    .is_some_and(|s| s.on_stop);
+            if on_stop && handler.should_run_scheduled_backup() {
+                handler.run_backup().await;
+            }
+            return;
+        }
+        StopReason::Shutdown => {
+            channel.say(&handler.http, "終了しました").await.ok();
+            return;
+        }
+        StopReason::Restart => {
+            channel
+                .say(&handler.http, "再起動のため停止しました")
+                .await
+                .ok();
+            return;
+        }
+        StopReason::Kill => {
+            channel.say(&handler.http, "強制終了しました").await.ok();
+            return;
+        }
+        StopReason::Crash => {}
+    }
+
+    let code = match status.ok().and_then(|s| s.code()) {
+        Some(code) => code.to_string(),
+        None => "不明".to_string(),
+    };
+    let mut message =
+        CreateMessage::new().content(format!("サーバが異常終了しました (終了コード: {code})"));
+
+    // クラッシュレポートがあれば添付する
+    if let Some(report) = latest_crash_report(&handler.server.work_dir, started_at) {
+        if let Ok(attachment) = CreateAttachment::path(&report).await {
+            message = message.add_file(attachment);
+        }
+    }
+    channel.send_message(&handler.http, message).await.ok();
+
+    let Some(ref restart) = handler.server.restart else {
+        return;
+    };
+
+    let uptime = started_at.elapsed().unwrap_or_default();
+    let attempt = count_crash(&handler.crash_count, uptime);
+    if attempt > restart.max_attempts {
+        channel
+            .say(
+                &handler.http,
+                format!(
+                    "再起動の上限 ({}回) に達したため、自動再起動を中止しました。",
+                    restart.max_attempts
+                ),
+            )
+            .await
+            .ok();
+        return;
+    }
+
+    let delay = restart.delay_for(attempt);
+    channel
+        .say(
+            &handler.http,
+            format!(
+                "{}秒後にサーバを再起動します ({}/{}回目)",
+                delay.as_secs(),
+                attempt,
+                restart.max_attempts
+            ),
+        )
+        .await
+        .ok();
+
+    tokio::time::sleep(delay).await;
+
+    // 待機中に手動で起動された場合は何もしない
+    if handler.is_server_running().await {
+        return;
+    }
+    handler.start_server().await;
+}
+
+/// Count a crash and return the number of consecutive crashes.
+///
+/// The count starts over if the server had been running for `STABLE_UPTIME` before crashing.
+fn count_crash(crash_count: &AtomicU32, uptime: Duration) -> u32 {
+    if uptime >= STABLE_UPTIME {
+        crash_count.store(0, Ordering::SeqCst);
+    }
+    crash_count.fetch_add(1, Ordering::SeqCst) + 1
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn count_consecutive_crashes() {
+        let crash_count = AtomicU32::new(0);
+
+        assert_eq!(count_crash(&crash_count, Duration::from_secs(5)), 1);
+        assert_eq!(count_crash(&crash_count, Duration::from_secs(5)), 2);
+        // 安定して稼働した後のクラッシュは1回目から数え直す
+        assert_eq!(count_crash(&crash_count, STABLE_UPTIME), 1);
+        assert_eq!(count_crash(&crash_count, Duration::from_secs(5)), 2);
+    }
+}
@@ -0,0 +1,36 @@
+use super::Handler;
+use crate::schedule::format_duration;
+
+impl Handler {
+    /// オンラインのプレイヤーと、参加した日時、接続元を表示します。
+    pub async fn mcplayers(&self) {
+        if !self.is_server_running().await {
+            self.send_message("起動していません！").await.ok();
+            return;
+        }
+
+        let players = self.players.online();
+        if players.is_empty() {
+            self.send_message("オンラインのプレイヤーはいません。")
+                .await
+                .ok();
+            return;
+        }
+
+        let now = chrono::Local::now();
+        let mut message = format!("オンラインのプレイヤー: {}人\n```\n", players.len());
+        for player in players {
+            let elapsed = (now - player.since).to_std().unwrap_or_default();
+            message.push_str(&format!(
+                "{:<16}  {}から ({})  {}\n",
+                player.name,
+                player.since.format("%m/%d %H:%M"),
+                format_duration(elapsed),
+                player.address.as_deref().unwrap_or("不明")
+            ));
+        }
+        message.push_str("```");
+
+        self.send_message(message).await.ok();
+    }
+}
@@ -0,0 +1,192 @@
+//! Restarts of the server with countdown warnings.
+use super::Handler;
+use crate::schedule::{format_duration, parse_duration};
+use crate::types::StopReason;
+use serde_json::json;
+use std::sync::atomic::Ordering;
+use std::time::{Duration, Instant};
+
+impl Handler {
+    /// `!mcrestart [待ち時間]` でサーバを再起動します。
+    pub async fn mcrestart(&self, args: Vec<&str>) -> bool {
+        let delay = match args.first() {
+            None => Duration::ZERO,
+            Some(arg) => match parse_duration(arg) {
+                Ok(delay) => delay,
+                Err(err) => {
+                    self.send_message(format!(
+                        "使い方: `!mcrestart [待ち時間]` (例: `!mcrestart 10m`)\n{err}"
+                    ))
+                    .await
+                    .ok();
+                    return false;
+                }
+            },
+        };
+
+        if !self.is_server_running().await {
+            self.send_message("起動していません！").await.ok();
+            return false;
+        }
+
+        self.restart_server(delay).await
+    }
+
+    /// プレイヤーに予告してから、`delay` 後にサーバを再起動します。
+    ///
+    /// 再起動できた場合は `true` を返します。
+    pub async fn restart_server(&self, delay: Duration) -> bool {
+        if self.restart_pending.swap(true, Ordering::SeqCst) {
+            self.send_message("すでに再起動を予定しています。")
+                .await
+                .ok();
+            return false;
+        }
+
+        let restarted = if self.count_down(delay).await {
+            self.stop_and_start().await
+        } else {
+            self.send_message("サーバが停止したため、再起動を中止しました。")
+                .await
+                .ok();
+            false
+        };
+
+        self.restart_pending.store(false, Ordering::SeqCst);
+        if !restarted {
+            // 再起動の予定中は待ち受けを始めないので、停止したままならここで始める
+            self.start_wake_listener().await;
+        }
+        restarted
+    }
+
+    /// 設定されたタイミングで再起動を予告しながら待機します。
+    ///
+    /// 待機中にサーバが停止した場合は `false` を返します。
+    async fn count_down(&self, delay: Duration) -> bool {
+        if !delay.is_zero() {
+            self.send_message(format!(
+                "{}後にサーバを再起動します。",
+                format_duration(delay)
+            ))
+            .await
+            .ok();
+        }
+
+        let warnings = self.server.restart_warnings().unwrap_or_default();
+        let mut remaining = delay;
+        for warning in warnings.into_iter().filter(|w| *w <= delay) {
+            tokio::time::sleep(remaining - warning).await;
+            remaining = warning;
+
+            if !self.is_server_running().await {
+                return false;
+            }
+            self.warn_restart(warning).await;
+        }
+        tokio::time::sleep(remaining).await;
+
+        self.is_server_running().await
+    }
+
+    /// ゲーム内のプレイヤーに再起動を予告します。
+    async fn warn_restart(&self, remaining: Duration) {
+        let text = format!("サーバは{}後に再起動します", format_duration(remaining));
+        self.send_console(&format!("say {text}")).await;
+        self.send_console(&format!("title @a title {}", json!({ "text": text })))
+            .await;
+    }
+
+    /// サーバを停止して、終了を待ってから起動します。
+    async fn stop_and_start(&self) -> bool {
+        // 通知を取りこぼさないように、stopを送信する前に待機を登録する
+        let exited = self.server_exit.notified();
+
+        let pid = {
+            let mut process = self.process.lock().await;
+            let Some(process) = process.as_mut() else {
+                return false;
+            };
+            process.stop_requested_at = Some(Instant::now());
+            process.pid
+        };
+
+        self.send_message("サーバを再起動しています……").await.ok();
+        self.stop_reason.record(StopReason::Restart);
+        if !self.send_console("stop").await {
+            // 停止していないので、`!mcend` を受け付けるようにする
+            if let Some(process) = self.process.lock().await.as_mut() {
+                process.stop_requested_at = None;
+            }
+            self.send_message("再起動できませんでした。サーバに `stop` を送信できません。")
+                .await
+                .ok();
+            return false;
+        }
+
+        let exited = match tokio::time::timeout(self.stop_timeout(), exited).await {
+            Ok(_) => None,
+            Err(_) => Some(self.server_exit.notified()),
+        };
+        if let Some(exited) = exited {
+            self.send_message(format!(
+                "サーバが{}秒以内に終了しなかったため、強制終了します。",
+                self.stop_timeout().as_secs()
+            ))
+            .await
+            .ok();
+
+            if let Err(err) = self.kill_server(pid, exited).await {
+                self.send_message(format!("強制終了できませんでした。\n{err}"))
+                    .await
+                    .ok();
+                return false;
+            }
+        }
+
+        self.mcstart().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use std::net::TcpListener;
+
+    #[tokio::test]
+    async fn start_wake_listener_after_aborted_restart() {
+        let port = TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let config: Config = toml::from_str(&format!(
+            r#"
+            [client]
+            secret = "TOKEN"
+
+            [permission]
+            channel_id = 1
+
+            [server]
+            work_dir = "srv"
+            port = {port}
+            jar_file = "server.jar"
+            auto_stop = false
+            memory = "4G"
+
+            [server.wake_on_connect]
+            "#
+        ))
+        .unwrap();
+        let server = config.managed_servers().unwrap().remove(0);
+        let handler = Handler::new(config, server);
+
+        // 予告中にサーバが停止した
+        assert!(!handler.restart_server(Duration::ZERO).await);
+
+        assert!(!handler.restart_pending.load(Ordering::SeqCst));
+        assert!(handler.wake_listener.lock().await.is_some());
+    }
+}
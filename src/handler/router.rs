@@ -0,0 +1,268 @@
+//! Routes Discord events to the handler of each managed server.
+use super::command::parse_command;
+use super::slash::{self, member_roles, reply, string_option};
+use super::Handler;
+use crate::audit::{AuditEntry, AuditResult};
+use crate::config::Config;
+use serenity::all::{ChannelId, ChannelType, Interaction, Message, Reaction, Ready, User};
+use serenity::prelude::*;
+use std::process::exit;
+
+#[derive(Clone)]
+pub struct Router {
+    config: Config,
+    handlers: Vec<Handler>,
+}
+
+impl Router {
+    pub fn new(config: Config, handlers: Vec<Handler>) -> Router {
+        Router { config, handlers }
+    }
+
+    fn is_managed_channel(&self, id: ChannelId) -> bool {
+        self.handlers.iter().any(|h| h.is_allowed_channel(id))
+    }
+
+    fn find(&self, name: &str) -> Option<&Handler> {
+        self.handlers.iter().find(|h| h.name() == name)
+    }
+
+    /// Find the handler from the server name or the channel.
+    ///
+    /// The server name may be given as the first argument. Returns the rest of the arguments.
+    fn route<'a>(
+        &self,
+        channel: ChannelId,
+        args: Vec<&'a str>,
+    ) -> Result<(&Handler, Vec<&'a str>), String> {
+        if let Some(handler) = args.first().and_then(|name| self.find(name)) {
+            return Ok((handler, args[1..].to_vec()));
+        }
+
+        let mut handlers = self
+            .handlers
+            .iter()
+            .filter(|h| h.is_allowed_channel(channel));
+        match (handlers.next(), handlers.next()) {
+            (Some(handler), None) => Ok((handler, args)),
+            _ => Err(format!(
+                "サーバの名前を指定してください: {}",
+                self.server_names().join(", ")
+            )),
+        }
+    }
+
+    fn server_names(&self) -> Vec<&str> {
+        self.handlers.iter().map(|h| h.name()).collect()
+    }
+
+    /// Record an action which does not target a particular server to the audit log.
+    fn audit(&self, user: &User, command: &str, args: &[&str], result: AuditResult) {
+        let entry = AuditEntry {
+            timestamp: chrono::Local::now().to_rfc3339(),
+            user_id: user.id.get(),
+            user_tag: user.tag(),
+            server: String::new(),
+            command: command.to_string(),
+            args: args.iter().map(|arg| arg.to_string()).collect(),
+            result,
+        };
+
+        // 監査ログのパスはすべてのサーバで共通
+        if let Err(err) = self.handlers[0].audit_log.append(&entry) {
+            println!("監査ログを書き込めませんでした: {err}");
+        }
+    }
+
+    /// Minecraftサーバをすべて終了してから、クライアントを終了します。
+    pub async fn mcsvend(&self) {
+        let tasks: Vec<_> = self
+            .handlers
+            .iter()
+            .cloned()
+            .map(|handler| tokio::spawn(async move { handler.shutdown_server().await }))
+            .collect();
+        for task in tasks {
+            task.await.ok();
+        }
+
+        let mut channels: Vec<ChannelId> = Vec::new();
+        for handler in self.handlers.iter() {
+            if !channels.contains(&handler.channel_id()) {
+                channels.push(handler.channel_id());
+            }
+        }
+        for channel in channels {
+            channel
+                .say(&self.handlers[0].http, "クライアントを終了しました。")
+                .await
+                .ok();
+        }
+        exit(0);
+    }
+}
+
+#[serenity::async_trait]
+impl EventHandler for Router {
+    async fn message(&self, _: Context, msg: Message) {
+        // ブリッジのチャンネルのメッセージはゲーム内に送る
+        if !msg.content.starts_with('!') {
+            for handler in self.handlers.iter() {
+                if handler.is_bridge_channel(msg.channel_id) {
+                    handler.relay_to_game(&msg).await;
+                }
+            }
+        }
+
+        if !self.is_managed_channel(msg.channel_id)
+            || !self.config.client.message_commands.unwrap_or(true)
+        {
+            return;
+        }
+
+        let Some(args) = parse_command(&msg.content) else {
+            return;
+        };
+        let command = args[0];
+
+        // 権限のないユーザには、サーバの一覧も含めて何も返さない
+        let roles = msg
+            .member
+            .as_ref()
+            .map(|m| m.roles.clone())
+            .unwrap_or_default();
+        let Some(level) = self.config.permission.level_of(msg.author.id, &roles) else {
+            return;
+        };
+
+        // クライアントの停止はサーバによらないので、サーバを選ぶ前に処理する
+        if command == "mcsvend" {
+            let args = &args[1..];
+            if level < self.config.permission.required_level(command) {
+                self.audit(&msg.author, command, args, AuditResult::Denied);
+                msg.channel_id
+                    .say(
+                        &self.handlers[0].http,
+                        "このコマンドを実行する権限がありません。",
+                    )
+                    .await
+                    .ok();
+                return;
+            }
+
+            // 終了してしまうので、実行する前に記録する
+            self.audit(&msg.author, command, args, AuditResult::Accepted);
+            self.mcsvend().await;
+            return;
+        }
+
+        let (handler, args) = match self.route(msg.channel_id, args[1..].to_vec()) {
+            Ok(v) => v,
+            Err(message) => {
+                msg.channel_id
+                    .say(&self.handlers[0].http, message)
+                    .await
+                    .ok();
+                return;
+            }
+        };
+
+        if !handler.authorize(&msg.author, &roles, command, &args).await {
+            return;
+        }
+
+        let result = handler
+            .run_command(command, args.clone(), msg.author.id)
+            .await;
+        handler.audit(&msg.author, command, &args, result);
+    }
+
+    async fn reaction_add(&self, _: Context, reaction: Reaction) {
+        for handler in self.handlers.iter() {
+            handler.approve(reaction.clone()).await;
+        }
+    }
+
+    async fn interaction_create(&self, ctx: Context, interaction: Interaction) {
+        match interaction {
+            Interaction::Command(command) => {
+                let roles = member_roles(&command);
+                if !self.is_managed_channel(command.channel_id)
+                    || self
+                        .config
+                        .permission
+                        .level_of(command.user.id, &roles)
+                        .is_none()
+                {
+                    reply(&ctx, &command, "このコマンドを実行する権限がありません。").await;
+                    return;
+                }
+
+                let name = string_option(&command, "server");
+                let (handler, _) = match self.route(command.channel_id, name.into_iter().collect())
+                {
+                    Ok(v) => v,
+                    Err(message) => {
+                        reply(&ctx, &command, &message).await;
+                        return;
+                    }
+                };
+                handler.slash_command(&ctx, command).await;
+            }
+            Interaction::Autocomplete(command) => {
+                // 補完候補はサーバによらない
+                self.handlers[0].autocomplete(&ctx, command).await;
+            }
+            _ => {}
+        }
+    }
+
+    async fn ready(&self, ctx: Context, ready: Ready) {
+        println!("Discordに接続しました。");
+        println!("BOTの名前: {}", ready.user.tag());
+
+        let mut guilds = Vec::new();
+        for handler in self.handlers.iter() {
+            let Ok(channel) = ctx.http.get_channel(handler.channel_id()).await else {
+                println!("設定で指定されているチャンネルが見つかりません。permission.channel_id の値を修正してください。");
+                println!("* BOTがチャンネルのあるサーバに参加しているか確認してください。");
+                exit(-1);
+            };
+
+            let Some(channel) = channel.guild() else {
+                println!("プライベートチャンネル、チャンネルカテゴリーを管理用チャンネルに指定することはできません。permission.channel_id の値を修正してください。");
+                exit(-1);
+            };
+
+            // テキストチャンネルであることを確認
+            if ChannelType::Text != channel.kind {
+                println!("ボイスチャンネルやスレッド、フォーラムなどを管理用チャンネルに指定することはできません。テキストチャンネルを指定してください。");
+                exit(-1);
+            }
+
+            println!(
+                "管理チャンネル ({}): {} (in {})",
+                handler.name(),
+                channel.name(),
+                channel
+                    .guild_id
+                    .to_partial_guild(&ctx.http)
+                    .await
+                    .unwrap()
+                    .name
+            );
+
+            if !guilds.contains(&channel.guild_id) {
+                guilds.push(channel.guild_id);
+            }
+        }
+
+        // スラッシュコマンドを登録する
+        let commands = slash::commands(&self.server_names());
+        for guild in guilds {
+            if let Err(err) = guild.set_commands(&ctx.http, commands.clone()).await {
+                println!("スラッシュコマンドを登録できませんでした: {err}");
+            }
+        }
+    }
+}
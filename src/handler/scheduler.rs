@@ -0,0 +1,78 @@
+use super::Handler;
+use crate::config::{BackupScheduleConfig, ScheduledRestartConfig};
+use crate::schedule::Schedule;
+use std::sync::atomic::Ordering;
+use std::time::Duration;
+
+impl Handler {
+    /// 設定された日時にバックアップを実行し続けます。
+    pub async fn backup_scheduler(self, schedule: Schedule) {
+        loop {
+            let Some(wait) = schedule.duration_until_next(chrono::Local::now()) else {
+                println!(
+                    "次の定期バックアップの日時が見つからないため、定期バックアップを終了します。"
+                );
+                return;
+            };
+            tokio::time::sleep(wait).await;
+
+            if !self.should_run_scheduled_backup() {
+                println!("前回のバックアップ以降にプレイヤーが参加していないため、定期バックアップをスキップします。");
+                continue;
+            }
+
+            self.send_message("定期バックアップを実行します。")
+                .await
+                .ok();
+            self.run_backup().await;
+        }
+    }
+
+    /// 設定された日時にサーバを再起動し続けます。
+    pub async fn restart_scheduler(self, schedule: Schedule) {
+        loop {
+            let Some(wait) = schedule.duration_until_next(chrono::Local::now()) else {
+                println!("次の定期再起動の日時が見つからないため、定期再起動を終了します。");
+                return;
+            };
+
+            // 予告を始める分だけ早く待機を終える
+            let lead = self
+                .server
+                .restart_warnings()
+                .unwrap_or_default()
+                .first()
+                .map_or(Duration::ZERO, |w| (*w).min(wait));
+            tokio::time::sleep(wait - lead).await;
+
+            if !self.is_server_running().await {
+                println!("サーバが停止しているため、定期再起動をスキップします。");
+                tokio::time::sleep(lead).await;
+                continue;
+            }
+
+            self.send_message("定期再起動を実行します。").await.ok();
+            self.restart_server(lead).await;
+        }
+    }
+
+    pub fn restart_schedule(&self) -> Option<&ScheduledRestartConfig> {
+        self.server.scheduled_restart.as_ref()
+    }
+
+    pub fn backup_schedule(&self) -> Option<&BackupScheduleConfig> {
+        self.server.backup.as_ref()?.schedule.as_ref()
+    }
+
+    /// `only_if_players` が設定されている場合は、前回のバックアップ以降にプレイヤーが参加したかを返します。
+    pub fn should_run_scheduled_backup(&self) -> bool {
+        let only_if_players = self
+            .server
+            .backup
+            .as_ref()
+            .and_then(|b| b.schedule.as_ref())
+            .is_some_and(|s| s.only_if_players);
+
+        !only_if_players || self.played_since_backup.load(Ordering::SeqCst)
+    }
+}
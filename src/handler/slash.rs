@@ -0,0 +1,240 @@
+//! Discordのスラッシュコマンド
+use super::*;
+use serenity::all::{
+    CommandInteraction, CommandOptionType, CreateAutocompleteResponse, CreateCommand,
+    CreateCommandOption, CreateInteractionResponse, CreateInteractionResponseMessage,
+    EditInteractionResponse, ResolvedValue,
+};
+
+/// `/mcc` の補完候補
+const CONSOLE_COMMANDS: &[&str] = &[
+    "list",
+    "say",
+    "save-all",
+    "time set day",
+    "time set night",
+    "weather clear",
+    "whitelist add",
+    "whitelist remove",
+    "kick",
+    "ban",
+    "pardon",
+    "op",
+    "deop",
+    "difficulty",
+    "gamemode",
+    "tp",
+    "give",
+    "stop",
+];
+
+/// Discordが受け付ける補完候補の最大数
+const AUTOCOMPLETE_LIMIT: usize = 25;
+
+/// Application commands registered to the guilds of the managed channels.
+///
+/// When several servers are managed, each command takes the name of the server.
+pub fn commands(server_names: &[&str]) -> Vec<CreateCommand> {
+    let commands = vec![
+        CreateCommand::new("mcstart").description("Minecraftサーバを起動します"),
+        CreateCommand::new("mcstop").description("Minecraftサーバを停止します"),
+        CreateCommand::new("mcc")
+            .description("Minecraftサーバでコマンドを実行します")
+            .add_option(
+                CreateCommandOption::new(
+                    CommandOptionType::String,
+                    "command",
+                    "実行するコマンド (先頭の / は不要)",
+                )
+                .required(true)
+                .set_autocomplete(true),
+            ),
+        CreateCommand::new("mcbackup")
+            .description("ワールドのバックアップを作成します")
+            .add_option(
+                CreateCommandOption::new(CommandOptionType::String, "action", "操作")
+                    .add_string_choice("作成", "create")
+                    .add_string_choice("一覧", "list"),
+            ),
+        CreateCommand::new("mcstatus").description("Minecraftサーバの状態を表示します"),
+    ];
+
+    if server_names.len() < 2 {
+        return commands;
+    }
+
+    commands
+        .into_iter()
+        .map(|command| {
+            let option = server_names.iter().fold(
+                CreateCommandOption::new(
+                    CommandOptionType::String,
+                    "server",
+                    "対象のサーバ。省略時はチャンネルから決まります",
+                ),
+                |option, name| option.add_string_choice(*name, *name),
+            );
+            command.add_option(option)
+        })
+        .collect()
+}
+
+/// Get the candidates of `/mcc` which start with the input.
+fn complete_console_command(input: &str) -> Vec<&'static str> {
+    let input = input.trim_start_matches('/');
+    CONSOLE_COMMANDS
+        .iter()
+        .filter(|c| c.starts_with(input))
+        .take(AUTOCOMPLETE_LIMIT)
+        .copied()
+        .collect()
+}
+
+pub(super) fn string_option<'a>(command: &'a CommandInteraction, name: &str) -> Option<&'a str> {
+    command
+        .data
+        .options()
+        .into_iter()
+        .find(|opt| opt.name == name)
+        .and_then(|opt| match opt.value {
+            ResolvedValue::String(value) => Some(value),
+            _ => None,
+        })
+}
+
+impl Handler {
+    pub(super) async fn slash_command(&self, ctx: &Context, command: CommandInteraction) {
+        // `/mcstop` は `!mcend` と同じ権限で実行する
+        let name = match command.data.name.as_str() {
+            "mcstop" => "mcend",
+            name => name,
+        };
+        let roles = member_roles(&command);
+        let args: Vec<&str> = ["command", "action"]
+            .into_iter()
+            .filter_map(|option| string_option(&command, option))
+            .collect();
+        match self.check_permission(command.user.id, &roles, name) {
+            Some(true) => {}
+            permission => {
+                if permission.is_some() {
+                    self.audit(&command.user, name, &args, AuditResult::Denied);
+                }
+                reply(ctx, &command, "このコマンドを実行する権限がありません。").await;
+                return;
+            }
+        }
+        let result = self.audit_result(name, &args);
+
+        let done = match command.data.name.as_str() {
+            "mcstart" => {
+                reply(ctx, &command, "起動を受け付けました").await;
+                self.mcstart().await
+            }
+            "mcstop" => {
+                reply(ctx, &command, "停止を受け付けました").await;
+                send_stop_to_server(self).await
+            }
+            "mcc" => {
+                let input = string_option(&command, "command").unwrap_or_default();
+                let input = input.trim_start_matches('/');
+                if input.is_empty() {
+                    reply(ctx, &command, "引数を入力して下さい！").await;
+                    false
+                } else {
+                    // RCONの応答を待つ間に応答期限 (3秒) を過ぎないようにする
+                    command.defer_ephemeral(&ctx.http).await.ok();
+                    let output = self
+                        .request_console_command(command.user.id, input.to_string())
+                        .await
+                        .unwrap_or_else(|| "承認を依頼しました。".to_string());
+                    command
+                        .edit_response(&ctx.http, EditInteractionResponse::new().content(output))
+                        .await
+                        .ok();
+                    true
+                }
+            }
+            "mcbackup" => {
+                let args = match string_option(&command, "action") {
+                    Some("list") => vec!["list"],
+                    _ => vec![],
+                };
+                reply(ctx, &command, "バックアップの操作を受け付けました").await;
+                self.mcbackup(args).await
+            }
+            "mcstatus" => {
+                command.defer_ephemeral(&ctx.http).await.ok();
+                let status = self.status_message().await;
+                command
+                    .edit_response(&ctx.http, EditInteractionResponse::new().content(status))
+                    .await
+                    .ok();
+                true
+            }
+            _ => {
+                reply(ctx, &command, "存在しないコマンドです。").await;
+                false
+            }
+        };
+
+        let result = if done { result } else { AuditResult::Failed };
+        self.audit(&command.user, name, &args, result);
+    }
+
+    pub(super) async fn autocomplete(&self, ctx: &Context, command: CommandInteraction) {
+        let roles = member_roles(&command);
+        if self.check_permission(command.user.id, &roles, "mcc") != Some(true) {
+            return;
+        }
+
+        let Some(focused) = command.data.autocomplete() else {
+            return;
+        };
+
+        let mut response = CreateAutocompleteResponse::new();
+        for candidate in complete_console_command(focused.value) {
+            response = response.add_string_choice(candidate, candidate);
+        }
+
+        command
+            .create_response(&ctx.http, CreateInteractionResponse::Autocomplete(response))
+            .await
+            .ok();
+    }
+}
+
+pub(super) fn member_roles(command: &CommandInteraction) -> Vec<RoleId> {
+    command
+        .member
+        .as_ref()
+        .map(|m| m.roles.clone())
+        .unwrap_or_default()
+}
+
+/// 実行したユーザにだけ見えるメッセージで応答する
+pub(super) async fn reply(ctx: &Context, command: &CommandInteraction, content: &str) {
+    let message = CreateInteractionResponseMessage::new()
+        .content(content)
+        .ephemeral(true);
+    command
+        .create_response(&ctx.http, CreateInteractionResponse::Message(message))
+        .await
+        .ok();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn complete_by_prefix() {
+        assert_eq!(
+            complete_console_command("time"),
+            vec!["time set day", "time set night"]
+        );
+        assert_eq!(complete_console_command("/sa"), vec!["say", "save-all"]);
+        assert!(complete_console_command("foo").is_empty());
+        assert_eq!(complete_console_command("").len(), CONSOLE_COMMANDS.len());
+    }
+}
@@ -0,0 +1,75 @@
+//! Starts the server when a player tries to join while it is stopped.
+use super::Handler;
+use crate::server::{WakeListener, WakeMessages};
+use serenity::all::{CreateAllowedMentions, CreateMessage};
+use std::sync::atomic::Ordering;
+
+/// 停止中にサーバ一覧に表示する既定のMOTD
+const DEFAULT_MOTD: &str = "§7サーバは休止中です。接続すると起動します。";
+
+/// 接続したプレイヤーに表示する既定のメッセージ
+const DEFAULT_KICK_MESSAGE: &str = "サーバを起動しています。1分ほど待ってから再接続してください。";
+
+impl Handler {
+    /// サーバの停止中に、サーバのポートで接続を待ち受けます。
+    pub async fn start_wake_listener(&self) {
+        let Some(ref wake) = self.server.wake_on_connect else {
+            return;
+        };
+
+        let mut wake_listener = self.wake_listener.lock().await;
+        if wake_listener.is_some()
+            || self.is_server_running().await
+            || self.restart_pending.load(Ordering::SeqCst)
+        {
+            return;
+        }
+
+        let messages = WakeMessages {
+            motd: wake.motd.as_deref().unwrap_or(DEFAULT_MOTD).to_string(),
+            kick_message: wake
+                .kick_message
+                .as_deref()
+                .unwrap_or(DEFAULT_KICK_MESSAGE)
+                .to_string(),
+        };
+        let handler = self.clone();
+        let res = WakeListener::start(self.server.port, messages, move |player| {
+            let rt = tokio::runtime::Builder::new_current_thread()
+                .enable_all()
+                .build()
+                .unwrap();
+            rt.block_on(handler.wake_up(player));
+        });
+
+        match res {
+            Ok(listener) => *wake_listener = Some(listener),
+            Err(err) => {
+                self.send_message(format!(
+                    "ポート{}で接続を待ち受けられませんでした。\n{err}",
+                    self.server.port
+                ))
+                .await
+                .ok();
+            }
+        }
+    }
+
+    /// 接続したプレイヤーのために、サーバを起動します。
+    async fn wake_up(&self, player: String) {
+        // 待ち受けはすでに終了してポートを解放している
+        self.wake_listener.lock().await.take();
+
+        println!("{player} が接続しようとしたため、サーバを起動します。");
+        let message = CreateMessage::new()
+            .content(format!(
+                "`{player}` が接続しようとしたため、サーバを起動します。"
+            ))
+            .allowed_mentions(CreateAllowedMentions::new());
+        self.channel_id()
+            .send_message(&self.http, message)
+            .await
+            .ok();
+        self.mcstart().await;
+    }
+}
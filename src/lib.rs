@@ -3,41 +3,118 @@ use serenity::prelude::*;
 use std::path::Path;
 use std::process::exit;
 
+mod audit;
 mod config;
 use config::Config;
 
 mod handler;
 mod server;
-use handler::Handler;
+use handler::{Handler, Router};
+use schedule::Schedule;
 
 mod context;
 mod save;
+mod schedule;
 mod types;
 
 pub async fn start() {
+    let config = ConfigContext::get();
     let Config {
         client: client_cfg,
-        server: server_cfg,
+        bridge: bridge_cfg,
         ..
-    } = ConfigContext::get();
-
-    let server_path = Path::new(&server_cfg.work_dir).join(&server_cfg.jar_file);
-    if !server_path.exists() {
-        let current = std::env::current_dir().unwrap();
-        let current = current.to_str().unwrap();
-        println!(
-            "サーバが存在しません。{}に置いてください",
-            Path::new(current).join(server_path).display()
-        );
+    } = config;
+
+    let servers = config.managed_servers().unwrap_or_else(|err| {
+        println!("設定に誤りがあります: {}", err);
         exit(-1);
+    });
+
+    for server_cfg in &servers {
+        for file in server_cfg.launch_files() {
+            let server_path = Path::new(&server_cfg.work_dir).join(file);
+            if !server_path.exists() {
+                let current = std::env::current_dir().unwrap();
+                let current = current.to_str().unwrap();
+                println!(
+                    "サーバが存在しません。{}に置いてください",
+                    Path::new(current).join(server_path).display()
+                );
+                exit(-1);
+            }
+        }
+
+        // 共通のバックアップフォルダの中に、サーバごとのフォルダを作る
+        if let Some(ref backup) = server_cfg.backup {
+            if !backup.output_dir.exists() && backup.output_dir.parent().is_some_and(|p| p.is_dir())
+            {
+                std::fs::create_dir(&backup.output_dir).ok();
+            }
+        }
+    }
+
+    // スラッシュコマンドだけを使う場合は特権インテントを要求しない
+    let use_bridge = bridge_cfg.is_some() || servers.iter().any(|s| s.bridge.is_some());
+    let intents = if client_cfg.message_commands.unwrap_or(true) || use_bridge {
+        GatewayIntents::GUILD_MESSAGES
+            | GatewayIntents::DIRECT_MESSAGES
+            | GatewayIntents::MESSAGE_CONTENT
+    } else {
+        GatewayIntents::empty()
+    };
+    // コマンドの承認に使う
+    let intents = intents | GatewayIntents::GUILD_MESSAGE_REACTIONS;
+
+    let handlers: Vec<Handler> = servers
+        .into_iter()
+        .map(|server_cfg| Handler::new(config.clone(), server_cfg))
+        .collect();
+
+    // 定期バックアップ
+    for handler in &handlers {
+        let Some(schedule_cfg) = handler.backup_schedule() else {
+            continue;
+        };
+        let schedule = Schedule::new(schedule_cfg.interval, schedule_cfg.cron.as_deref())
+            .unwrap_or_else(|err| {
+                println!("定期バックアップの設定に誤りがあります: {}", err);
+                exit(-1);
+            });
+        tokio::spawn(handler.clone().backup_scheduler(schedule));
+    }
+
+    // 定期再起動
+    for handler in &handlers {
+        let Some(restart_cfg) = handler.restart_schedule() else {
+            continue;
+        };
+        let schedule = Schedule::new(restart_cfg.interval, restart_cfg.cron.as_deref())
+            .unwrap_or_else(|err| {
+                println!("定期再起動の設定に誤りがあります: {}", err);
+                exit(-1);
+            });
+        tokio::spawn(handler.clone().restart_scheduler(schedule));
     }
 
-    let intents = GatewayIntents::GUILD_MESSAGES
-        | GatewayIntents::DIRECT_MESSAGES
-        | GatewayIntents::MESSAGE_CONTENT;
+    // 停止中のサーバへの接続を待ち受ける
+    for handler in &handlers {
+        handler.start_wake_listener().await;
+    }
+
+    let router = Router::new(config.clone(), handlers);
+
+    // Ctrl+C や SIGTERM を受け取ったら、Minecraftサーバを停止してから終了する
+    {
+        let router = router.clone();
+        tokio::spawn(async move {
+            wait_for_shutdown_signal().await;
+            println!("終了シグナルを受信しました。");
+            router.mcsvend().await;
+        });
+    }
 
     let mut client = Client::builder(&client_cfg.secret, intents)
-        .event_handler(Handler::new(ConfigContext::get().clone()))
+        .event_handler(router)
         .await
         .expect("Err creating client");
 
@@ -45,3 +122,20 @@ pub async fn start() {
         println!("Client error: {:?}", e);
     }
 }
+
+/// Wait for Ctrl+C (and SIGTERM on Unix).
+async fn wait_for_shutdown_signal() {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+
+        let mut sigterm = signal(SignalKind::terminate()).expect("Err registering SIGTERM handler");
+        tokio::select! {
+            _ = tokio::signal::ctrl_c() => {},
+            _ = sigterm.recv() => {},
+        }
+    }
+
+    #[cfg(not(unix))]
+    tokio::signal::ctrl_c().await.ok();
+}
@@ -1 +1,3 @@
 pub mod backup;
+pub mod restore;
+pub mod retention;
@@ -1,26 +1,41 @@
-use crate::{config::Config, context::ConfigContext};
-use std::{fs, io, path::Path};
-use zip::ZipWriter;
-use zip_extensions::ZipWriterExtensions;
-
-pub fn save_backup() {
-    let Config {
-        server: server_conf,
-        backup: backup_conf,
-        ..
-    } = ConfigContext::get();
-
-    let Some(backup_conf) = backup_conf else {
-        return;
+use super::retention::{ARCHIVE_PREFIX, ARCHIVE_TIME_FORMAT};
+use crate::{
+    config::{BackupConfig, ServerConfig},
+    server::level_name,
+};
+use glob::{MatchOptions, Pattern};
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+use zip::{write::SimpleFileOptions, ZipWriter};
+
+/// `exclude` の設定によらず、常に除外するパターン
+const DEFAULT_EXCLUDES: &[&str] = &["session.lock"];
+
+/// Create a backup archive of the world of the server and return its path.
+///
+/// This function does not stop the server from writing the world.
+/// Send `save-off` and `save-all flush` before calling it while the server is running.
+pub fn save_backup(server_conf: &ServerConfig) -> io::Result<PathBuf> {
+    let Some(ref backup_conf) = server_conf.backup else {
+        return Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            "バックアップが設定されていません",
+        ));
     };
 
     let server_dir = Path::new(&server_conf.work_dir);
     let output_dir = Path::new(&backup_conf.output_dir);
 
-    read_save_and_write(server_dir, output_dir).unwrap();
+    read_save_and_write(server_dir, output_dir, backup_conf)
 }
 
-fn read_save_and_write(server_dir: &Path, output_dir: &Path) -> io::Result<()> {
+fn read_save_and_write(
+    server_dir: &Path,
+    output_dir: &Path,
+    backup_conf: &BackupConfig,
+) -> io::Result<PathBuf> {
     if !output_dir.exists() {
         return Err(io::Error::new(
             io::ErrorKind::NotFound,
@@ -28,26 +43,312 @@ fn read_save_and_write(server_dir: &Path, output_dir: &Path) -> io::Result<()> {
         ));
     };
 
-    let world_dir = Path::new(&server_dir).join("world");
+    let paths = backup_paths(server_dir, backup_conf);
+    if paths.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            "バックアップするワールドが見つかりません",
+        ));
+    }
+
+    let excludes = exclude_patterns(backup_conf)?;
 
     let now = chrono::Local::now();
-    let filename = format!("world_backup_{}", now.format("%Y%m%d_%H%M%S"));
+    let filename = format!("{ARCHIVE_PREFIX}{}", now.format(ARCHIVE_TIME_FORMAT));
 
     // Create a backup archive
-    let zip = output_dir.join(filename).with_extension("zip");
-    let zip = ZipWriter::new(fs::File::create(zip)?);
-    if let Err(e) = zip.create_from_directory(&world_dir) {
-        return match e {
-            zip::result::ZipError::Io(err) => Err(io::Error::new(
-                io::ErrorKind::Other,
-                format!("zipファイルを作成できませんでした: {err}"),
-            )),
-            _ => Err(io::Error::new(
-                io::ErrorKind::Other,
-                format!("zipファイルを作成できませんでした: {e}"),
-            )),
-        };
+    let zip_path = output_dir.join(filename).with_extension("zip");
+    if let Err(e) = write_archive(server_dir, &paths, &excludes, &zip_path) {
+        // 作成途中のファイルを残さない
+        fs::remove_file(&zip_path).ok();
+
+        return Err(io::Error::new(
+            e.kind(),
+            format!("zipファイルを作成できませんでした: {e}"),
+        ));
     };
 
+    Ok(zip_path)
+}
+
+/// Paths to back up, relative to `server_dir`.
+///
+/// The world directories are detected from `level-name` in `server.properties`,
+/// including the dimensions split by Bukkit-based servers. `include` is added to them.
+fn backup_paths(server_dir: &Path, backup_conf: &BackupConfig) -> Vec<PathBuf> {
+    let level = level_name(server_dir);
+    let worlds = [
+        level.clone(),
+        format!("{level}_nether"),
+        format!("{level}_the_end"),
+    ];
+
+    let candidates = worlds
+        .into_iter()
+        .map(PathBuf::from)
+        .chain(backup_conf.include.iter().flatten().cloned());
+
+    let mut paths: Vec<PathBuf> = Vec::new();
+    for path in candidates {
+        if server_dir.join(&path).exists() && !paths.contains(&path) {
+            paths.push(path);
+        }
+    }
+    paths
+}
+
+/// Patterns of paths excluded from backups.
+pub(super) fn exclude_patterns(backup_conf: &BackupConfig) -> io::Result<Vec<ExcludePattern>> {
+    DEFAULT_EXCLUDES
+        .iter()
+        .copied()
+        .chain(backup_conf.exclude.iter().flatten().map(String::as_str))
+        .map(ExcludePattern::new)
+        .collect()
+}
+
+/// A pattern of paths excluded from backups, similar to `.gitignore`.
+///
+/// - A pattern without `/` matches the name of a file or directory at any depth.
+/// - A pattern with `/` matches the path relative to the server directory.
+/// - A pattern ending with `/` matches only directories.
+pub(super) struct ExcludePattern {
+    pattern: Pattern,
+    name_only: bool,
+    dir_only: bool,
+}
+
+impl ExcludePattern {
+    fn new(pattern: &str) -> io::Result<ExcludePattern> {
+        let dir_only = pattern.ends_with('/');
+        let pattern = pattern.trim_end_matches('/');
+        let name_only = !pattern.contains('/');
+
+        let pattern = Pattern::new(pattern).map_err(|err| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("除外パターンが不正です: {pattern}: {err}"),
+            )
+        })?;
+
+        Ok(ExcludePattern {
+            pattern,
+            name_only,
+            dir_only,
+        })
+    }
+
+    /// `relative` は `/` 区切りのサーバディレクトリからの相対パス
+    pub(super) fn matches(&self, relative: &str, is_dir: bool) -> bool {
+        if self.dir_only && !is_dir {
+            return false;
+        }
+
+        let options = MatchOptions {
+            require_literal_separator: true,
+            ..MatchOptions::new()
+        };
+        let target = if self.name_only {
+            relative.rsplit('/').next().unwrap_or(relative)
+        } else {
+            relative
+        };
+
+        self.pattern.matches_with(target, options)
+    }
+}
+
+fn write_archive(
+    server_dir: &Path,
+    paths: &[PathBuf],
+    excludes: &[ExcludePattern],
+    zip_path: &Path,
+) -> io::Result<()> {
+    let mut zip = ZipWriter::new(fs::File::create(zip_path)?);
+    let options = SimpleFileOptions::default();
+
+    // (ディスク上のパス, アーカイブ内の名前)
+    let mut stack: Vec<(PathBuf, String)> = paths
+        .iter()
+        .map(|path| (server_dir.join(path), to_archive_name(path)))
+        .collect();
+
+    while let Some((path, name)) = stack.pop() {
+        let is_dir = path.is_dir();
+        if excludes.iter().any(|e| e.matches(&name, is_dir)) {
+            continue;
+        }
+
+        if is_dir {
+            zip.add_directory(name.as_str(), options)?;
+            for entry in fs::read_dir(&path)? {
+                let entry = entry?;
+                // リンク先が外部や親ディレクトリの場合があるので、シンボリックリンクはたどらない
+                if entry.file_type()?.is_symlink() {
+                    continue;
+                }
+                let child = format!("{name}/{}", entry.file_name().to_string_lossy());
+                stack.push((entry.path(), child));
+            }
+        } else {
+            zip.start_file(name.as_str(), options)?;
+            io::copy(&mut fs::File::open(&path)?, &mut zip)?;
+        }
+    }
+
+    zip.finish()?;
     Ok(())
 }
+
+/// `/` 区切りのアーカイブ内の名前に変換する
+pub(super) fn to_archive_name(path: &Path) -> String {
+    path.components()
+        .map(|c| c.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use zip::ZipArchive;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("hone-test-{}-{}", name, std::process::id()));
+        fs::remove_dir_all(&dir).ok();
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn write(path: PathBuf) {
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, "data").unwrap();
+    }
+
+    fn backup_conf(output_dir: PathBuf) -> BackupConfig {
+        BackupConfig {
+            output_dir,
+            retention: None,
+            schedule: None,
+            include: None,
+            exclude: None,
+        }
+    }
+
+    fn archive_files(path: &Path) -> Vec<String> {
+        let zip = ZipArchive::new(fs::File::open(path).unwrap()).unwrap();
+        let mut names: Vec<String> = zip
+            .file_names()
+            .filter(|name| !name.ends_with('/'))
+            .map(str::to_string)
+            .collect();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn back_up_all_dimensions_of_level() {
+        let dir = temp_dir("backup-dimensions");
+        let server_dir = dir.join("server");
+        fs::create_dir_all(dir.join("output")).unwrap();
+
+        fs::create_dir_all(&server_dir).unwrap();
+        fs::write(
+            server_dir.join("server.properties"),
+            "level-name=survival\n",
+        )
+        .unwrap();
+        write(server_dir.join("survival/level.dat"));
+        write(server_dir.join("survival/session.lock"));
+        write(server_dir.join("survival/region/r.0.0.mca"));
+        write(server_dir.join("survival_nether/DIM-1/region/r.0.0.mca"));
+        write(server_dir.join("world/level.dat"));
+
+        let archive = read_save_and_write(
+            &server_dir,
+            &dir.join("output"),
+            &backup_conf(dir.join("output")),
+        )
+        .unwrap();
+
+        assert_eq!(
+            archive_files(&archive),
+            vec![
+                "survival/level.dat",
+                "survival/region/r.0.0.mca",
+                "survival_nether/DIM-1/region/r.0.0.mca",
+            ]
+        );
+
+        fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn include_and_exclude_paths() {
+        let dir = temp_dir("backup-include");
+        let server_dir = dir.join("server");
+        fs::create_dir_all(dir.join("output")).unwrap();
+
+        write(server_dir.join("world/level.dat"));
+        write(server_dir.join("world/session.lock"));
+        write(server_dir.join("ops.json"));
+        write(server_dir.join("plugins/example/config.yml"));
+        write(server_dir.join("plugins/example/logs/latest.log"));
+        write(server_dir.join("plugins/example.jar"));
+
+        let conf = BackupConfig {
+            include: Some(vec![
+                PathBuf::from("ops.json"),
+                PathBuf::from("plugins"),
+                PathBuf::from("whitelist.json"),
+            ]),
+            exclude: Some(vec!["logs/".to_string(), "plugins/*.jar".to_string()]),
+            ..backup_conf(dir.join("output"))
+        };
+        let archive = read_save_and_write(&server_dir, &dir.join("output"), &conf).unwrap();
+
+        // `exclude` を指定しても session.lock は除外される
+        assert_eq!(
+            archive_files(&archive),
+            vec!["ops.json", "plugins/example/config.yml", "world/level.dat"]
+        );
+
+        fs::remove_dir_all(&dir).ok();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn skip_symbolic_links() {
+        let dir = temp_dir("backup-symlink");
+        let server_dir = dir.join("server");
+        fs::create_dir_all(dir.join("output")).unwrap();
+
+        write(server_dir.join("world/level.dat"));
+        write(dir.join("secret.txt"));
+        std::os::unix::fs::symlink(dir.join("secret.txt"), server_dir.join("world/secret.txt"))
+            .unwrap();
+        // たどると無限にループする
+        std::os::unix::fs::symlink(&server_dir, server_dir.join("world/loop")).unwrap();
+
+        let conf = backup_conf(dir.join("output"));
+        let archive = read_save_and_write(&server_dir, &dir.join("output"), &conf).unwrap();
+
+        assert_eq!(archive_files(&archive), vec!["world/level.dat"]);
+
+        fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn exclude_pattern() {
+        let name = ExcludePattern::new("*.lock").unwrap();
+        assert!(name.matches("world/session.lock", false));
+
+        let dir = ExcludePattern::new("logs/").unwrap();
+        assert!(dir.matches("plugins/a/logs", true));
+        assert!(!dir.matches("plugins/a/logs", false));
+
+        let path = ExcludePattern::new("plugins/*.jar").unwrap();
+        assert!(path.matches("plugins/a.jar", false));
+        assert!(!path.matches("plugins/a/b.jar", false));
+    }
+}
@@ -0,0 +1,361 @@
+use super::backup::{exclude_patterns, to_archive_name, ExcludePattern};
+use crate::{config::BackupConfig, server::level_name};
+use std::{
+    collections::BTreeSet,
+    fs, io,
+    path::{Component, Path, PathBuf},
+};
+use zip::ZipArchive;
+
+/// Restore the files from a backup archive and return the path of the safety copy.
+///
+/// The files which will be overwritten are moved aside before extracting. If the extraction
+/// or the verification fails, the extracted files are removed and the safety copy is moved back.
+/// The files excluded from backups are copied back from the safety copy after extracting.
+/// Entries which would be extracted outside the server directory, e.g. `../`, are skipped.
+/// The server must be stopped.
+pub fn restore_backup(
+    server_dir: &Path,
+    archive: &Path,
+    backup_conf: &BackupConfig,
+) -> io::Result<Option<PathBuf>> {
+    let excludes = exclude_patterns(backup_conf)?;
+    let mut zip = ZipArchive::new(fs::File::open(archive)?)?;
+
+    // 古い形式のバックアップはワールドの中身だけを含む
+    let legacy = zip.file_names().any(|name| name == "level.dat");
+    let (targets, extract_dir) = if legacy {
+        let level = level_name(server_dir);
+        (vec![PathBuf::from(&level)], server_dir.join(level))
+    } else {
+        (top_level_entries(&mut zip)?, server_dir.to_path_buf())
+    };
+
+    // 上書きされるファイルを退避する
+    let now = chrono::Local::now();
+    let safety_copy = server_dir.join(format!("before_restore_{}", now.format("%Y%m%d_%H%M%S")));
+    let mut moved = Vec::new();
+    for target in &targets {
+        let path = server_dir.join(target);
+        if path.exists() {
+            fs::create_dir_all(&safety_copy)?;
+            fs::rename(&path, safety_copy.join(target))?;
+            moved.push(target);
+        }
+    }
+
+    if let Err(err) = extract_and_verify(&mut zip, &extract_dir, server_dir) {
+        // 展開途中のファイルを削除して、退避したファイルを戻す
+        for target in &targets {
+            let path = server_dir.join(target);
+            if path.is_dir() {
+                fs::remove_dir_all(&path).ok();
+            } else {
+                fs::remove_file(&path).ok();
+            }
+        }
+        for target in moved {
+            fs::rename(safety_copy.join(target), server_dir.join(target))?;
+        }
+        fs::remove_dir(&safety_copy).ok();
+
+        return Err(err);
+    }
+
+    // バックアップに含まれないファイルは、復元で消えないように戻す
+    for target in moved {
+        copy_excluded(
+            &safety_copy.join(target),
+            &server_dir.join(target),
+            &to_archive_name(target),
+            &excludes,
+        )?;
+    }
+
+    Ok(safety_copy.exists().then_some(safety_copy))
+}
+
+/// Names of the files and directories at the top level of the archive.
+///
+/// Entries which would be extracted outside the directory, e.g. `../`, are ignored.
+fn top_level_entries(zip: &mut ZipArchive<fs::File>) -> io::Result<Vec<PathBuf>> {
+    let mut entries = BTreeSet::new();
+    for i in 0..zip.len() {
+        let Some(name) = zip.by_index_raw(i)?.enclosed_name() else {
+            continue;
+        };
+        if let Some(Component::Normal(entry)) = name.components().next() {
+            entries.insert(PathBuf::from(entry));
+        }
+    }
+
+    Ok(entries.into_iter().collect())
+}
+
+/// Copy the files matching the exclude patterns from `from` to `to` unless they already exist.
+///
+/// `name` is the `/`-separated path of `from` relative to the server directory.
+fn copy_excluded(
+    from: &Path,
+    to: &Path,
+    name: &str,
+    excludes: &[ExcludePattern],
+) -> io::Result<()> {
+    let is_dir = from.is_dir();
+    if excludes.iter().any(|e| e.matches(name, is_dir)) {
+        return copy_missing(from, to);
+    }
+
+    if is_dir {
+        for entry in fs::read_dir(from)? {
+            let entry = entry?;
+            let file_name = entry.file_name();
+            let child = format!("{name}/{}", file_name.to_string_lossy());
+            copy_excluded(&entry.path(), &to.join(&file_name), &child, excludes)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Copy a file or a directory recursively, skipping the files which already exist.
+fn copy_missing(from: &Path, to: &Path) -> io::Result<()> {
+    if from.is_dir() {
+        fs::create_dir_all(to)?;
+        for entry in fs::read_dir(from)? {
+            let entry = entry?;
+            copy_missing(&entry.path(), &to.join(entry.file_name()))?;
+        }
+    } else if !to.exists() {
+        if let Some(parent) = to.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::copy(from, to)?;
+    }
+
+    Ok(())
+}
+
+/// Extract the entries of the archive, skipping the ones outside `extract_dir` and symbolic links.
+fn extract(zip: &mut ZipArchive<fs::File>, extract_dir: &Path) -> io::Result<()> {
+    for i in 0..zip.len() {
+        let mut entry = zip.by_index(i)?;
+        let Some(name) = entry.enclosed_name() else {
+            continue;
+        };
+        if entry.is_symlink() {
+            continue;
+        }
+
+        let path = extract_dir.join(name);
+        if entry.is_dir() {
+            fs::create_dir_all(&path)?;
+            continue;
+        }
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        // 読み終えたときにCRCが検証される
+        io::copy(&mut entry, &mut fs::File::create(&path)?)?;
+    }
+
+    Ok(())
+}
+
+fn extract_and_verify(
+    zip: &mut ZipArchive<fs::File>,
+    extract_dir: &Path,
+    server_dir: &Path,
+) -> io::Result<()> {
+    extract(zip, extract_dir)
+        .map_err(|err| io::Error::other(format!("バックアップを展開できませんでした: {err}")))?;
+
+    // server.properties も復元される場合があるので、展開後にワールド名を読む
+    let level = level_name(server_dir);
+    if !server_dir.join(&level).join("level.dat").is_file() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("復元したワールド {level} に level.dat がありません"),
+        ));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use zip::{write::SimpleFileOptions, ZipWriter};
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("hone-test-{}-{}", name, std::process::id()));
+        fs::remove_dir_all(&dir).ok();
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn backup_conf(dir: &Path) -> BackupConfig {
+        BackupConfig {
+            output_dir: dir.to_path_buf(),
+            retention: None,
+            schedule: None,
+            include: None,
+            exclude: None,
+        }
+    }
+
+    fn create_archive(dir: &Path, files: &[(&str, &str)]) -> PathBuf {
+        let archive = dir.join("backup.zip");
+        let mut zip = ZipWriter::new(fs::File::create(&archive).unwrap());
+        for (name, content) in files {
+            zip.start_file(*name, SimpleFileOptions::default()).unwrap();
+            io::Write::write_all(&mut zip, content.as_bytes()).unwrap();
+        }
+        zip.finish().unwrap();
+        archive
+    }
+
+    #[test]
+    fn restore_world_and_keep_safety_copy() {
+        let dir = temp_dir("restore");
+        let archive = create_archive(
+            &dir,
+            &[
+                ("world/level.dat", "restored"),
+                ("world_nether/DIM-1/r.0.0.mca", "restored"),
+                ("ops.json", "restored"),
+            ],
+        );
+        fs::create_dir_all(dir.join("world")).unwrap();
+        fs::write(dir.join("world/level.dat"), "current").unwrap();
+        fs::write(dir.join("ops.json"), "current").unwrap();
+
+        let safety_copy = restore_backup(&dir, &archive, &backup_conf(&dir))
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(
+            fs::read_to_string(dir.join("world/level.dat")).unwrap(),
+            "restored"
+        );
+        assert_eq!(
+            fs::read_to_string(dir.join("ops.json")).unwrap(),
+            "restored"
+        );
+        assert!(dir.join("world_nether/DIM-1/r.0.0.mca").is_file());
+        assert_eq!(
+            fs::read_to_string(safety_copy.join("world/level.dat")).unwrap(),
+            "current"
+        );
+        assert_eq!(
+            fs::read_to_string(safety_copy.join("ops.json")).unwrap(),
+            "current"
+        );
+
+        fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn restore_legacy_archive() {
+        let dir = temp_dir("restore-legacy");
+        let archive = create_archive(&dir, &[("level.dat", "restored")]);
+        fs::create_dir_all(dir.join("world")).unwrap();
+        fs::write(dir.join("world/level.dat"), "current").unwrap();
+
+        let safety_copy = restore_backup(&dir, &archive, &backup_conf(&dir))
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(
+            fs::read_to_string(dir.join("world/level.dat")).unwrap(),
+            "restored"
+        );
+        assert_eq!(
+            fs::read_to_string(safety_copy.join("world/level.dat")).unwrap(),
+            "current"
+        );
+
+        fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn roll_back_when_verification_fails() {
+        let dir = temp_dir("restore-rollback");
+        let archive = create_archive(&dir, &[("world/readme.txt", "not a world")]);
+        fs::create_dir_all(dir.join("world")).unwrap();
+        fs::write(dir.join("world/level.dat"), "current").unwrap();
+
+        assert!(restore_backup(&dir, &archive, &backup_conf(&dir)).is_err());
+        assert_eq!(
+            fs::read_to_string(dir.join("world/level.dat")).unwrap(),
+            "current"
+        );
+        assert!(!dir.join("world/readme.txt").exists());
+
+        fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn keep_files_excluded_from_backup() {
+        let dir = temp_dir("restore-excluded");
+        let archive = create_archive(
+            &dir,
+            &[
+                ("world/level.dat", "restored"),
+                ("plugins/example/config.yml", "restored"),
+            ],
+        );
+        fs::create_dir_all(dir.join("world")).unwrap();
+        fs::write(dir.join("world/level.dat"), "current").unwrap();
+        fs::write(dir.join("world/stale.dat"), "current").unwrap();
+        fs::create_dir_all(dir.join("plugins/example")).unwrap();
+        fs::write(dir.join("plugins/example.jar"), "current").unwrap();
+        fs::write(dir.join("plugins/example/config.yml"), "current").unwrap();
+
+        let conf = BackupConfig {
+            exclude: Some(vec!["plugins/*.jar".to_string()]),
+            ..backup_conf(&dir)
+        };
+        restore_backup(&dir, &archive, &conf).unwrap();
+
+        assert_eq!(
+            fs::read_to_string(dir.join("plugins/example.jar")).unwrap(),
+            "current"
+        );
+        assert_eq!(
+            fs::read_to_string(dir.join("plugins/example/config.yml")).unwrap(),
+            "restored"
+        );
+        // 除外されていないファイルはバックアップの時点に戻る
+        assert!(!dir.join("world/stale.dat").exists());
+
+        fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn skip_entries_outside_server_dir() {
+        let dir = temp_dir("restore-traversal");
+        let server_dir = dir.join("server");
+        fs::create_dir_all(server_dir.join("world")).unwrap();
+        fs::write(server_dir.join("world/level.dat"), "current").unwrap();
+        let archive = create_archive(
+            &dir,
+            &[
+                ("world/level.dat", "restored"),
+                ("../outside.txt", "evil"),
+                ("/absolute.txt", "evil"),
+            ],
+        );
+
+        restore_backup(&server_dir, &archive, &backup_conf(&dir)).unwrap();
+
+        assert_eq!(
+            fs::read_to_string(server_dir.join("world/level.dat")).unwrap(),
+            "restored"
+        );
+        assert!(!dir.join("outside.txt").exists());
+        assert!(!Path::new("/absolute.txt").exists());
+
+        fs::remove_dir_all(&dir).ok();
+    }
+}
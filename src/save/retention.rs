@@ -0,0 +1,271 @@
+//! Retention policy of backup archives.
+use crate::config::RetentionConfig;
+use chrono::{Datelike, NaiveDateTime};
+use std::{
+    collections::HashSet,
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+/// バックアップファイル名の接頭辞
+pub const ARCHIVE_PREFIX: &str = "world_backup_";
+
+/// バックアップファイル名に含まれる日時の書式
+pub const ARCHIVE_TIME_FORMAT: &str = "%Y%m%d_%H%M%S";
+
+/// A backup archive in the output directory.
+#[derive(Clone, Debug)]
+pub struct Archive {
+    pub path: PathBuf,
+    pub time: NaiveDateTime,
+    pub size: u64,
+}
+
+impl Archive {
+    pub fn name(&self) -> String {
+        self.path
+            .file_name()
+            .unwrap_or_default()
+            .to_string_lossy()
+            .into_owned()
+    }
+}
+
+/// List the backup archives in `output_dir`, newest first.
+pub fn list_archives(output_dir: &Path) -> io::Result<Vec<Archive>> {
+    let mut archives: Vec<Archive> = fs::read_dir(output_dir)?
+        .filter_map(|entry| {
+            let entry = entry.ok()?;
+            let path = entry.path();
+            if path.extension()? != "zip" {
+                return None;
+            }
+
+            let stem = path.file_stem()?.to_str()?;
+            let time = stem.strip_prefix(ARCHIVE_PREFIX)?;
+            let time = NaiveDateTime::parse_from_str(time, ARCHIVE_TIME_FORMAT).ok()?;
+            let size = entry.metadata().ok()?.len();
+
+            Some(Archive { path, time, size })
+        })
+        .collect();
+
+    archives.sort_by_key(|a| std::cmp::Reverse(a.time));
+    Ok(archives)
+}
+
+/// Select the archives to delete under the retention policy.
+///
+/// `archives` must be sorted newest first. The newest archive is always kept.
+pub fn select_expired(archives: &[Archive], retention: &RetentionConfig) -> Vec<PathBuf> {
+    let has_generation_rule = retention.keep_last.is_some()
+        || retention.keep_daily.is_some()
+        || retention.keep_weekly.is_some()
+        || retention.keep_monthly.is_some();
+
+    let mut keep: Vec<bool> = vec![!has_generation_rule; archives.len()];
+
+    if let Some(n) = retention.keep_last {
+        keep.iter_mut().take(n).for_each(|k| *k = true);
+    }
+
+    // 各期間で最も新しいものを、新しい期間から順に n 個残す
+    let mut keep_per_period = |n: Option<usize>, period: fn(&NaiveDateTime) -> (i32, u32)| {
+        let Some(n) = n else {
+            return;
+        };
+
+        let mut periods = HashSet::new();
+        for (i, archive) in archives.iter().enumerate() {
+            if periods.len() >= n {
+                break;
+            }
+            if periods.insert(period(&archive.time)) {
+                keep[i] = true;
+            }
+        }
+    };
+    keep_per_period(retention.keep_daily, |t| (t.year(), t.ordinal()));
+    keep_per_period(retention.keep_weekly, |t| {
+        let week = t.iso_week();
+        (week.year(), week.week())
+    });
+    keep_per_period(retention.keep_monthly, |t| (t.year(), t.month()));
+
+    // 設定の読み込み時に確認している
+    if let Ok(Some(max_total_size)) = retention.max_total_size() {
+        let mut total = 0u64;
+        for (i, archive) in archives.iter().enumerate() {
+            if !keep[i] {
+                continue;
+            }
+            total = total.saturating_add(archive.size);
+            if total > max_total_size {
+                keep[i] = false;
+            }
+        }
+    }
+
+    // 最新のバックアップは必ず残す
+    if let Some(k) = keep.first_mut() {
+        *k = true;
+    }
+
+    archives
+        .iter()
+        .zip(keep)
+        .filter(|(_, keep)| !keep)
+        .map(|(archive, _)| archive.path.clone())
+        .collect()
+}
+
+/// Delete the expired archives in `output_dir` and return the deleted paths.
+pub fn prune_archives(output_dir: &Path, retention: &RetentionConfig) -> io::Result<Vec<PathBuf>> {
+    let archives = list_archives(output_dir)?;
+    let expired = select_expired(&archives, retention);
+
+    for path in &expired {
+        fs::remove_file(path)?;
+    }
+
+    Ok(expired)
+}
+
+/// Parse a size such as `500M` or `10G`.
+pub fn parse_size(s: &str) -> Option<u64> {
+    let s = s.trim();
+    let (number, unit) = match s.find(|c: char| !c.is_ascii_digit()) {
+        Some(i) => s.split_at(i),
+        None => (s, ""),
+    };
+
+    let number: u64 = number.parse().ok()?;
+    let unit: u64 = match unit.trim().to_ascii_uppercase().trim_end_matches('B') {
+        "" => 1,
+        "K" => 1024,
+        "M" => 1024 * 1024,
+        "G" => 1024 * 1024 * 1024,
+        "T" => 1024 * 1024 * 1024 * 1024,
+        _ => return None,
+    };
+
+    number.checked_mul(unit)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn archive(time: &str, size: u64) -> Archive {
+        Archive {
+            path: PathBuf::from(format!("{ARCHIVE_PREFIX}{time}.zip")),
+            time: NaiveDateTime::parse_from_str(time, ARCHIVE_TIME_FORMAT).unwrap(),
+            size,
+        }
+    }
+
+    fn retention() -> RetentionConfig {
+        RetentionConfig {
+            keep_last: None,
+            keep_daily: None,
+            keep_weekly: None,
+            keep_monthly: None,
+            max_total_size: None,
+        }
+    }
+
+    fn names(paths: Vec<PathBuf>) -> Vec<String> {
+        paths
+            .iter()
+            .map(|p| p.to_string_lossy().into_owned())
+            .collect()
+    }
+
+    #[test]
+    fn keep_everything_without_rules() {
+        let archives = vec![archive("20250103_000000", 1), archive("20250102_000000", 1)];
+        assert!(select_expired(&archives, &retention()).is_empty());
+    }
+
+    #[test]
+    fn keep_last_n() {
+        let archives = vec![
+            archive("20250103_000000", 1),
+            archive("20250102_000000", 1),
+            archive("20250101_000000", 1),
+        ];
+        let retention = RetentionConfig {
+            keep_last: Some(2),
+            ..retention()
+        };
+
+        assert_eq!(
+            names(select_expired(&archives, &retention)),
+            vec!["world_backup_20250101_000000.zip"]
+        );
+    }
+
+    #[test]
+    fn keep_generations() {
+        let archives = vec![
+            archive("20250210_120000", 1),
+            archive("20250210_060000", 1),
+            archive("20250209_060000", 1),
+            archive("20250201_060000", 1),
+            archive("20250115_060000", 1),
+            archive("20250105_060000", 1),
+        ];
+        let retention = RetentionConfig {
+            keep_daily: Some(2),
+            keep_monthly: Some(2),
+            ..retention()
+        };
+
+        // 日: 02/10 12:00, 02/09 / 月: 02/10 12:00, 01/15
+        assert_eq!(
+            names(select_expired(&archives, &retention)),
+            vec![
+                "world_backup_20250210_060000.zip",
+                "world_backup_20250201_060000.zip",
+                "world_backup_20250105_060000.zip",
+            ]
+        );
+    }
+
+    #[test]
+    fn limit_total_size() {
+        let archives = vec![
+            archive("20250103_000000", 600),
+            archive("20250102_000000", 300),
+            archive("20250101_000000", 300),
+        ];
+        let retention = RetentionConfig {
+            max_total_size: Some("1K".to_string()),
+            ..retention()
+        };
+
+        assert_eq!(
+            names(select_expired(&archives, &retention)),
+            vec!["world_backup_20250101_000000.zip"]
+        );
+    }
+
+    #[test]
+    fn always_keep_newest() {
+        let archives = vec![archive("20250103_000000", 2048)];
+        let retention = RetentionConfig {
+            max_total_size: Some("1K".to_string()),
+            ..retention()
+        };
+
+        assert!(select_expired(&archives, &retention).is_empty());
+    }
+
+    #[test]
+    fn parse_size_correctly() {
+        assert_eq!(parse_size("100"), Some(100));
+        assert_eq!(parse_size("500M"), Some(500 * 1024 * 1024));
+        assert_eq!(parse_size("10GB"), Some(10 * 1024 * 1024 * 1024));
+        assert_eq!(parse_size("10X"), None);
+    }
+}
@@ -0,0 +1,378 @@
+//! Schedules for periodic tasks, given as an interval or a cron expression.
+use chrono::{
+    DateTime, Datelike, Duration as ChronoDuration, Local, NaiveDateTime, NaiveTime, TimeZone,
+    Timelike,
+};
+use std::time::Duration;
+
+/// 次の実行日時を探す範囲 (分)。`0 0 29 2 *` でも見つかるように5年分
+const SEARCH_LIMIT_MINUTES: i64 = 5 * 366 * 24 * 60;
+
+pub enum Schedule {
+    Interval(Duration),
+    Cron(Cron),
+}
+
+impl Schedule {
+    /// Create a schedule from an interval in minutes or a cron expression.
+    /// Exactly one of them must be given.
+    pub fn new(interval_minutes: Option<u64>, cron: Option<&str>) -> Result<Schedule, String> {
+        match (interval_minutes, cron) {
+            (Some(0), None) => Err("間隔には1以上を指定してください".to_string()),
+            (Some(minutes), None) => Ok(Schedule::Interval(Duration::from_secs(minutes * 60))),
+            (None, Some(cron)) => Ok(Schedule::Cron(Cron::parse(cron)?)),
+            (Some(_), Some(_)) => Err("間隔とcronはどちらか一方だけ指定してください".to_string()),
+            (None, None) => Err("間隔またはcronを指定してください".to_string()),
+        }
+    }
+
+    /// Get the duration from `now` until the next run.
+    pub fn duration_until_next(&self, now: DateTime<Local>) -> Option<Duration> {
+        match self {
+            Schedule::Interval(interval) => Some(*interval),
+            Schedule::Cron(cron) => {
+                let next = cron.next_after(now.naive_local())?;
+                // 夏時間の切り替えで存在しない時刻は飛ばす
+                let next = Local.from_local_datetime(&next).earliest()?;
+                (next - now).to_std().ok()
+            }
+        }
+    }
+}
+
+/// A cron expression with 5 fields: minute, hour, day of month, month and day of week.
+///
+/// Each field accepts `*`, numbers, ranges (`1-5`), lists (`1,3`) and steps (`*/15`).
+#[derive(Debug, PartialEq, Eq)]
+pub struct Cron {
+    minutes: u64,
+    hours: u64,
+    days: u64,
+    months: u64,
+    weekdays: u64,
+    any_day: bool,
+    any_weekday: bool,
+}
+
+impl Cron {
+    pub fn parse(expr: &str) -> Result<Cron, String> {
+        let fields: Vec<&str> = expr.split_whitespace().collect();
+        let [minute, hour, day, month, weekday] = fields[..] else {
+            return Err(format!(
+                "cronは5つのフィールド (分 時 日 月 曜日) で指定してください: {expr}"
+            ));
+        };
+
+        let mut weekdays = parse_field(weekday, 0, 7)?;
+        // 7も日曜日として扱う
+        if weekdays & (1 << 7) != 0 {
+            weekdays |= 1;
+        }
+
+        Ok(Cron {
+            minutes: parse_field(minute, 0, 59)?,
+            hours: parse_field(hour, 0, 23)?,
+            days: parse_field(day, 1, 31)?,
+            months: parse_field(month, 1, 12)?,
+            weekdays,
+            any_day: day == "*",
+            any_weekday: weekday == "*",
+        })
+    }
+
+    /// Find the next time after `time` which matches the expression.
+    pub fn next_after(&self, time: NaiveDateTime) -> Option<NaiveDateTime> {
+        let mut t = time.with_second(0)?.with_nanosecond(0)? + ChronoDuration::minutes(1);
+        let limit = t + ChronoDuration::minutes(SEARCH_LIMIT_MINUTES);
+
+        while t < limit {
+            if !has(self.months, t.month()) {
+                // 翌月の1日へ
+                let (year, month) = match t.month() {
+                    12 => (t.year() + 1, 1),
+                    m => (t.year(), m + 1),
+                };
+                t = t
+                    .date()
+                    .with_day(1)?
+                    .with_year(year)?
+                    .with_month(month)?
+                    .and_hms_opt(0, 0, 0)?;
+                continue;
+            }
+            if !self.matches_day(&t) {
+                t = (t.date() + ChronoDuration::days(1)).and_hms_opt(0, 0, 0)?;
+                continue;
+            }
+            if !has(self.hours, t.hour()) {
+                t = t.with_minute(0)? + ChronoDuration::hours(1);
+                continue;
+            }
+            if !has(self.minutes, t.minute()) {
+                t += ChronoDuration::minutes(1);
+                continue;
+            }
+            return Some(t);
+        }
+
+        None
+    }
+
+    /// 日と曜日の両方が指定されている場合は、どちらかに一致すればよい
+    fn matches_day(&self, t: &NaiveDateTime) -> bool {
+        let day = has(self.days, t.day());
+        let weekday = has(self.weekdays, t.weekday().num_days_from_sunday());
+
+        match (self.any_day, self.any_weekday) {
+            (true, true) => true,
+            (true, false) => weekday,
+            (false, true) => day,
+            (false, false) => day || weekday,
+        }
+    }
+}
+
+fn has(mask: u64, value: u32) -> bool {
+    mask & (1 << value) != 0
+}
+
+/// Parse a field into a bit mask.
+fn parse_field(field: &str, min: u32, max: u32) -> Result<u64, String> {
+    let invalid = || format!("cronのフィールドが不正です: {field}");
+    let mut mask = 0u64;
+
+    for part in field.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => (range, step.parse::<u32>().map_err(|_| invalid())?),
+            None => (part, 1),
+        };
+        if step == 0 {
+            return Err(invalid());
+        }
+
+        let (start, end) = if range == "*" {
+            (min, max)
+        } else if let Some((start, end)) = range.split_once('-') {
+            (
+                start.parse().map_err(|_| invalid())?,
+                end.parse().map_err(|_| invalid())?,
+            )
+        } else {
+            let value = range.parse().map_err(|_| invalid())?;
+            // `5/10` は5から最大値まで
+            (value, if part.contains('/') { max } else { value })
+        };
+
+        if start < min || end > max || start > end {
+            return Err(invalid());
+        }
+
+        for value in (start..=end).step_by(step as usize) {
+            mask |= 1 << value;
+        }
+    }
+
+    Ok(mask)
+}
+
+/// A range of time in a day such as `18:00-23:00`. It may cross midnight, e.g. `22:00-06:00`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TimeRange {
+    start: NaiveTime,
+    end: NaiveTime,
+}
+
+impl TimeRange {
+    pub fn parse(s: &str) -> Result<TimeRange, String> {
+        let invalid = || format!("時間帯は HH:MM-HH:MM の形式で指定してください: {s}");
+
+        let (start, end) = s.split_once('-').ok_or_else(invalid)?;
+        let parse = |t: &str| NaiveTime::parse_from_str(t.trim(), "%H:%M").map_err(|_| invalid());
+
+        Ok(TimeRange {
+            start: parse(start)?,
+            end: parse(end)?,
+        })
+    }
+
+    /// Whether the time is in the range. The end is not included.
+    pub fn contains(&self, time: NaiveTime) -> bool {
+        // 開始と終了が同じなら終日
+        if self.start < self.end {
+            self.start <= time && time < self.end
+        } else {
+            self.start <= time || time < self.end
+        }
+    }
+}
+
+/// Parse a duration such as `10s`, `5m`, `2h` or `1h30m`. A number without a unit is in seconds.
+pub fn parse_duration(s: &str) -> Result<Duration, String> {
+    let invalid = || format!("時間の指定が不正です: {s} (例: 10s, 5m, 2h)");
+
+    if let Ok(secs) = s.parse::<u64>() {
+        return Ok(Duration::from_secs(secs));
+    }
+
+    let mut total: u64 = 0;
+    let mut number = String::new();
+    for c in s.chars() {
+        if c.is_ascii_digit() {
+            number.push(c);
+            continue;
+        }
+
+        let unit = match c {
+            's' => 1,
+            'm' => 60,
+            'h' => 60 * 60,
+            'd' => 24 * 60 * 60,
+            _ => return Err(invalid()),
+        };
+        let value: u64 = number.parse().map_err(|_| invalid())?;
+        total = value
+            .checked_mul(unit)
+            .and_then(|secs| total.checked_add(secs))
+            .ok_or_else(invalid)?;
+        number.clear();
+    }
+    if !number.is_empty() || s.is_empty() {
+        return Err(invalid());
+    }
+
+    Ok(Duration::from_secs(total))
+}
+
+/// Format a duration in Japanese, e.g. `1時間30分` or `10秒`.
+pub fn format_duration(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    let (hours, minutes, seconds) = (secs / 3600, secs / 60 % 60, secs % 60);
+
+    let mut result = String::new();
+    if hours > 0 {
+        result.push_str(&format!("{hours}時間"));
+    }
+    if minutes > 0 {
+        result.push_str(&format!("{minutes}分"));
+    }
+    if seconds > 0 || result.is_empty() {
+        result.push_str(&format!("{seconds}秒"));
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn time(s: &str) -> NaiveDateTime {
+        NaiveDateTime::parse_from_str(s, "%Y-%m-%d %H:%M").unwrap()
+    }
+
+    #[test]
+    fn daily_at_4am() {
+        let cron = Cron::parse("0 4 * * *").unwrap();
+
+        assert_eq!(
+            cron.next_after(time("2025-01-01 03:59")),
+            Some(time("2025-01-01 04:00"))
+        );
+        assert_eq!(
+            cron.next_after(time("2025-01-01 04:00")),
+            Some(time("2025-01-02 04:00"))
+        );
+        assert_eq!(
+            cron.next_after(time("2025-12-31 05:00")),
+            Some(time("2026-01-01 04:00"))
+        );
+    }
+
+    #[test]
+    fn steps_lists_and_weekdays() {
+        let cron = Cron::parse("*/15 9-17 * * 1-5").unwrap();
+        // 2025-01-04 は土曜日
+        assert_eq!(
+            cron.next_after(time("2025-01-03 17:50")),
+            Some(time("2025-01-06 09:00"))
+        );
+        assert_eq!(
+            cron.next_after(time("2025-01-06 09:01")),
+            Some(time("2025-01-06 09:15"))
+        );
+
+        let cron = Cron::parse("30 3 1,15 * *").unwrap();
+        assert_eq!(
+            cron.next_after(time("2025-01-02 00:00")),
+            Some(time("2025-01-15 03:30"))
+        );
+
+        // 日曜日は0でも7でもよい
+        assert_eq!(Cron::parse("0 0 * * 7"), Cron::parse("0 0 * * 0,7"));
+    }
+
+    #[test]
+    fn leap_day() {
+        let cron = Cron::parse("0 0 29 2 *").unwrap();
+        assert_eq!(
+            cron.next_after(time("2025-03-01 00:00")),
+            Some(time("2028-02-29 00:00"))
+        );
+    }
+
+    #[test]
+    fn reject_invalid_expression() {
+        assert!(Cron::parse("0 4 * *").is_err());
+        assert!(Cron::parse("60 4 * * *").is_err());
+        assert!(Cron::parse("0 4 * * mon").is_err());
+        assert!(Cron::parse("*/0 4 * * *").is_err());
+    }
+
+    #[test]
+    fn create_schedule() {
+        assert!(Schedule::new(Some(60), None).is_ok());
+        assert!(Schedule::new(None, Some("0 4 * * *")).is_ok());
+        assert!(Schedule::new(Some(60), Some("0 4 * * *")).is_err());
+        assert!(Schedule::new(None, None).is_err());
+        assert!(Schedule::new(Some(0), None).is_err());
+    }
+
+    #[test]
+    fn parse_and_format_duration() {
+        assert_eq!(parse_duration("10s"), Ok(Duration::from_secs(10)));
+        assert_eq!(parse_duration("5m"), Ok(Duration::from_secs(300)));
+        assert_eq!(parse_duration("1h30m"), Ok(Duration::from_secs(5400)));
+        assert_eq!(parse_duration("90"), Ok(Duration::from_secs(90)));
+        assert!(parse_duration("").is_err());
+        assert!(parse_duration("5").is_ok());
+        assert!(parse_duration("m").is_err());
+        assert!(parse_duration("10x").is_err());
+        assert!(parse_duration("1h30").is_err());
+
+        assert_eq!(format_duration(Duration::from_secs(600)), "10分");
+        assert_eq!(format_duration(Duration::from_secs(5410)), "1時間30分10秒");
+        assert_eq!(format_duration(Duration::ZERO), "0秒");
+    }
+
+    #[test]
+    fn time_range() {
+        let t = |s: &str| NaiveTime::parse_from_str(s, "%H:%M").unwrap();
+
+        let evening = TimeRange::parse("18:00-23:00").unwrap();
+        assert!(evening.contains(t("18:00")));
+        assert!(evening.contains(t("22:59")));
+        assert!(!evening.contains(t("23:00")));
+
+        // 日付をまたぐ
+        let night = TimeRange::parse("22:00 - 06:00").unwrap();
+        assert!(night.contains(t("23:30")));
+        assert!(night.contains(t("05:59")));
+        assert!(!night.contains(t("12:00")));
+
+        assert!(TimeRange::parse("00:00-00:00")
+            .unwrap()
+            .contains(t("12:00")));
+
+        assert!(TimeRange::parse("18:00").is_err());
+        assert!(TimeRange::parse("25:00-26:00").is_err());
+    }
+}
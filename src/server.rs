@@ -1,29 +1,60 @@
 use crate::types::ServerMessage;
 use std::{
     cell::Cell,
+    collections::HashMap,
+    ffi::OsString,
     io::{self, BufRead, BufReader},
-    path::PathBuf,
-    process::{Child, ChildStderr, ChildStdin, ChildStdout, Stdio},
+    path::{Path, PathBuf},
+    process::{Child, ChildStderr, ChildStdin, ChildStdout, Command, ExitStatus, Stdio},
     sync::mpsc,
     thread,
 };
 
 mod stdin_sender;
 
+mod rcon;
+pub use rcon::*;
+
+mod protocol;
+
+mod properties;
+pub use properties::*;
+
+mod status;
+pub use status::*;
+
 mod create;
 pub use create::*;
 
 mod auto_stop;
 pub use auto_stop::*;
 
+mod crash_report;
+pub use crash_report::*;
+
+mod log_parser;
+pub use log_parser::*;
+
+mod players;
+pub use players::*;
+
+mod wake;
+pub use wake::*;
+
 pub struct ServerBuilder {
+    java_path: Option<PathBuf>,
     jar_file: Option<PathBuf>,
+    argfiles: Vec<PathBuf>,
+    launch_script: Option<PathBuf>,
     work_dir: Option<PathBuf>,
-    memory: Option<String>,
+    min_memory: Option<String>,
+    max_memory: Option<String>,
+    jvm_args: Vec<String>,
+    server_args: Vec<String>,
+    envs: HashMap<String, String>,
 }
 
 pub struct Server {
-    #[allow(dead_code)]
     proc: Child,
     stdin: Cell<Option<ChildStdin>>,
     stdout: Cell<Option<ChildStdout>>,
@@ -33,14 +64,42 @@ pub struct Server {
 impl ServerBuilder {
     pub fn new() -> Self {
         Self {
+            java_path: None,
             jar_file: None,
+            argfiles: Vec::new(),
+            launch_script: None,
             work_dir: None,
-            memory: None,
+            min_memory: None,
+            max_memory: None,
+            jvm_args: Vec::new(),
+            server_args: Vec::new(),
+            envs: HashMap::new(),
         }
     }
 
-    pub fn jar_file(mut self, jar_file: PathBuf) -> Self {
-        self.jar_file = Some(jar_file);
+    /// Set the path of `java`. `java` in `PATH` is used by default.
+    pub fn java_path(mut self, java_path: Option<PathBuf>) -> Self {
+        self.java_path = java_path;
+        self
+    }
+
+    pub fn jar_file(mut self, jar_file: Option<PathBuf>) -> Self {
+        self.jar_file = jar_file;
+        self
+    }
+
+    /// Launch the server with argument files (`@file`) instead of a jar file,
+    /// like `run.sh` of Forge and NeoForge.
+    pub fn argfiles(mut self, argfiles: Vec<PathBuf>) -> Self {
+        self.argfiles = argfiles;
+        self
+    }
+
+    /// Launch the server with a script such as `run.sh` instead of `java`.
+    ///
+    /// The script is run with the shell and receives `nogui` and the server arguments.
+    pub fn launch_script(mut self, script: Option<PathBuf>) -> Self {
+        self.launch_script = script;
         self
     }
 
@@ -49,37 +108,90 @@ impl ServerBuilder {
         self
     }
 
-    pub fn memory(mut self, memory: &str) -> Self {
-        self.memory = Some(memory.to_string());
+    pub fn min_memory(mut self, memory: Option<&str>) -> Self {
+        self.min_memory = memory.map(str::to_string);
         self
     }
 
-    pub fn build(self) -> io::Result<Server> {
-        let jar_file = self.jar_file.expect("jar_file is not set");
-        let work_dir = self.work_dir.expect("work_dir is not set");
-        let memory = self.memory.expect("memory is not set");
+    pub fn max_memory(mut self, memory: Option<&str>) -> Self {
+        self.max_memory = memory.map(str::to_string);
+        self
+    }
+
+    pub fn jvm_args(mut self, args: Vec<String>) -> Self {
+        self.jvm_args = args;
+        self
+    }
+
+    pub fn server_args(mut self, args: Vec<String>) -> Self {
+        self.server_args = args;
+        self
+    }
 
-        let server = Server::mcserver_new(&jar_file, &work_dir, &memory)?;
+    pub fn envs(mut self, envs: HashMap<String, String>) -> Self {
+        self.envs = envs;
+        self
+    }
+
+    pub fn build(self) -> io::Result<Server> {
+        let work_dir = self.work_dir.as_ref().expect("work_dir is not set");
+
+        let mut cmd = match self.launch_script {
+            Some(ref script) => {
+                let mut cmd = self::script_command_new(script);
+                cmd.arg("nogui").args(&self.server_args);
+                cmd
+            }
+            None => {
+                let java_path = self.java_path.as_deref().unwrap_or(Path::new("java"));
+                let mut cmd = self::command_new(java_path);
+                cmd.args(self.java_args());
+                cmd
+            }
+        };
+        cmd.envs(&self.envs).current_dir(work_dir);
+
+        let server = Server::mcserver_new(cmd)?;
 
         Ok(server)
     }
+
+    /// Arguments passed to `java`.
+    fn java_args(&self) -> Vec<OsString> {
+        let mut args: Vec<OsString> = Vec::new();
+
+        if let Some(ref memory) = self.min_memory {
+            args.push(format!("-Xms{}", memory).into());
+        }
+        if let Some(ref memory) = self.max_memory {
+            args.push(format!("-Xmx{}", memory).into());
+        }
+        args.extend(self.jvm_args.iter().map(OsString::from));
+
+        if self.argfiles.is_empty() {
+            let jar_file = self.jar_file.as_ref().expect("jar_file is not set");
+            args.push("-jar".into());
+            args.push(jar_file.into());
+        } else {
+            for argfile in &self.argfiles {
+                let mut arg = OsString::from("@");
+                arg.push(argfile);
+                args.push(arg);
+            }
+        }
+
+        args.push("nogui".into());
+        args.extend(self.server_args.iter().map(OsString::from));
+
+        args
+    }
 }
 
 impl Server {
     /// Create a new Minecraft server process.
-    fn mcserver_new(jar_file: &PathBuf, work_dir: &PathBuf, memory: &str) -> io::Result<Server> {
-        let xmx = &format!("-Xmx{}", memory);
-        let xms = &format!("-Xms{}", memory);
-
-        let mut cmd = self::command_new();
-        cmd.arg("java")
-            .args([xmx, xms])
-            .arg("-jar")
-            .arg(jar_file)
-            .arg("nogui")
-            .current_dir(work_dir)
-            // `stdin`, `stdout`, `stderr` must be set to `piped` to read/write from/to the child process.
-            .stdin(Stdio::piped())
+    fn mcserver_new(mut cmd: Command) -> io::Result<Server> {
+        // `stdin`, `stdout`, `stderr` must be set to `piped` to read/write from/to the child process.
+        cmd.stdin(Stdio::piped())
             .stdout(Stdio::piped())
             .stderr(Stdio::piped());
 
@@ -105,6 +217,16 @@ impl Server {
         stdin_sender::StdinSender::new(stdin).listen()
     }
 
+    /// Get the process ID of the server.
+    pub fn id(&self) -> u32 {
+        self.proc.id()
+    }
+
+    /// Wait for the server process to exit and get its exit status.
+    pub fn wait(&mut self) -> io::Result<ExitStatus> {
+        self.proc.wait()
+    }
+
     /// Get the server logs. You can only call this method once.
     pub fn logs(&self) -> mpsc::Receiver<ServerMessage> {
         let (stdout_tx, rx) = mpsc::channel::<ServerMessage>();
@@ -118,6 +240,8 @@ impl Server {
                 let mut stdout_reader = BufReader::new(stdout);
                 let mut buf = String::new();
 
+                let mut parser = LogParser::new();
+
                 while let Ok(lines) = stdout_reader.read_line(&mut buf) {
                     if lines == 0 {
                         break;
@@ -127,26 +251,40 @@ impl Server {
                     // 改行コードが既に含まれているのでprint!マクロを使う
                     print!("[Minecraft] {}", buf);
 
-                    // サーバの起動が完了したとき
-                    if buf.contains("Done") {
-                        stdout_tx.send(ServerMessage::Done).ok();
+                    let line = LogLine::parse(&buf);
+
+                    if let Some(ref line) = line {
+                        // サーバの起動が完了したとき
+                        if line.is_done() {
+                            stdout_tx.send(ServerMessage::Done).ok();
+                        }
+
+                        // EULAへの同意が必要な時
+                        if line.is_eula_required() {
+                            stdout_tx.send(ServerMessage::Error(
+                                "サーバを開始するには、EULAに同意する必要があります。eula.txtを編集してください。"
+                                    .to_string(),
+                            ))
+                            .ok();
+                        }
+
+                        // ワールドの保存が完了したとき
+                        if line.is_game_saved() {
+                            stdout_tx.send(ServerMessage::Saved).ok();
+                        }
+
+                        // Minecraftサーバ終了を検知
+                        if line.is_all_saved() {
+                            break;
+                        }
                     }
 
-                    // EULAへの同意が必要な時
-                    if buf.contains("You need to agree") {
-                        stdout_tx.send(ServerMessage::Error(
-                "サーバを開始するには、EULAに同意する必要があります。eula.txtを編集してください。"
-                    .to_string(),
-            ))
-            .ok();
-                    }
+                    stdout_tx.send(ServerMessage::Info(buf.clone())).unwrap();
 
-                    // Minecraftサーバ終了を検知
-                    if buf.contains("All dimensions are saved") {
-                        break;
+                    if let Some(event) = line.and_then(|line| parser.parse(&line)) {
+                        stdout_tx.send(ServerMessage::Event(event)).ok();
                     }
 
-                    stdout_tx.send(ServerMessage::Info(buf.clone())).unwrap();
                     buf.clear();
                 }
 
@@ -178,3 +316,49 @@ impl Server {
         rx
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn java_args_for_jar() {
+        let builder = ServerBuilder::new()
+            .jar_file(Some(PathBuf::from("server.jar")))
+            .min_memory(Some("1G"))
+            .max_memory(Some("4G"))
+            .jvm_args(vec!["-XX:+UseG1GC".to_string()])
+            .server_args(vec!["--port".to_string(), "25566".to_string()]);
+
+        assert_eq!(
+            builder.java_args(),
+            [
+                "-Xms1G",
+                "-Xmx4G",
+                "-XX:+UseG1GC",
+                "-jar",
+                "server.jar",
+                "nogui",
+                "--port",
+                "25566"
+            ]
+        );
+    }
+
+    #[test]
+    fn java_args_for_argfiles() {
+        let builder = ServerBuilder::new().argfiles(vec![
+            PathBuf::from("user_jvm_args.txt"),
+            PathBuf::from("libraries/net/neoforged/neoforge/21.1.0/unix_args.txt"),
+        ]);
+
+        assert_eq!(
+            builder.java_args(),
+            [
+                "@user_jvm_args.txt",
+                "@libraries/net/neoforged/neoforge/21.1.0/unix_args.txt",
+                "nogui"
+            ]
+        );
+    }
+}
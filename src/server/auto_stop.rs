@@ -1,6 +1,14 @@
+use super::PlayerRegistry;
+use crate::schedule::TimeRange;
+use crate::types::{StopReason, StopReasonRecorder};
+use chrono::{Local, NaiveTime};
 use std::{
-    sync::mpsc::{channel, RecvTimeoutError::*, SendError, Sender},
-    thread, time,
+    sync::{
+        mpsc::{channel, RecvTimeoutError::*, SendError, Sender},
+        Arc, Mutex,
+    },
+    thread,
+    time::{Duration, Instant},
 };
 
 type PlayerNotifierResult = Result<(), ()>;
@@ -23,12 +31,12 @@ impl PlayerNotifier {
         Ok(())
     }
 
-    /// Increment the player count.
+    /// Notify that a player joined. The player must be in the registry.
     pub fn join(&self) -> PlayerNotifierResult {
         Self::notifier_err_from(self.0.send(PlayerNotification::Join))
     }
 
-    /// Decrement the player count.
+    /// Notify that a player left.
     pub fn leave(&self) -> PlayerNotifierResult {
         Self::notifier_err_from(self.0.send(PlayerNotification::Leave))
     }
@@ -39,42 +47,191 @@ impl PlayerNotifier {
     }
 }
 
-pub fn auto_stop_inspect(stdin: Sender<String>, sec: u64) -> PlayerNotifier {
+/// 自動停止の条件を確認する間隔
+const TICK: Duration = Duration::from_secs(1);
+
+/// When the server is stopped automatically.
+#[derive(Clone, Default)]
+pub struct AutoStopPolicy {
+    /// プレイヤーがいない状態がこの時間続いたら停止する
+    pub idle: Duration,
+    /// 起動してからこの時間は停止しない
+    pub grace: Duration,
+    /// 停止するこの時間前に予告する
+    pub warning: Option<Duration>,
+    /// この時間帯は停止しない
+    pub keep_running: Vec<TimeRange>,
+}
+
+impl AutoStopPolicy {
+    fn is_keep_running(&self, now: NaiveTime) -> bool {
+        self.keep_running.iter().any(|range| range.contains(now))
+    }
+}
+
+/// The countdown of auto-stop.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AutoStopStatus {
+    /// プレイヤーがいるか、起動が完了していない
+    Waiting,
+    /// 停止までの残り時間
+    Countdown(Duration),
+    /// 一時停止中。期限があれば再開までの残り時間
+    Paused(Option<Duration>),
+}
+
+#[derive(Default)]
+struct State {
+    paused: bool,
+    paused_until: Option<Instant>,
+    deadline: Option<Instant>,
+}
+
+/// Runtime state of auto-stop shared with the watcher thread.
+///
+/// Auto-stop can be paused while the server is running.
+#[derive(Clone, Default)]
+pub struct AutoStopState(Arc<Mutex<State>>);
+
+impl AutoStopState {
+    /// Pause auto-stop, for `duration` if given.
+    pub fn pause(&self, duration: Option<Duration>) {
+        let mut state = self.0.lock().unwrap();
+        state.paused = true;
+        state.paused_until = duration.map(|d| Instant::now() + d);
+        state.deadline = None;
+    }
+
+    pub fn resume(&self) {
+        let mut state = self.0.lock().unwrap();
+        state.paused = false;
+        state.paused_until = None;
+    }
+
+    /// Forget the pause and the countdown, e.g. when the server starts.
+    pub fn reset(&self) {
+        *self.0.lock().unwrap() = Default::default();
+    }
+
+    pub fn status(&self) -> AutoStopStatus {
+        let now = Instant::now();
+        if self.is_paused(now) {
+            let state = self.0.lock().unwrap();
+            return AutoStopStatus::Paused(state.paused_until.map(|until| until - now));
+        }
+
+        match self.0.lock().unwrap().deadline {
+            Some(deadline) => AutoStopStatus::Countdown(deadline.saturating_duration_since(now)),
+            None => AutoStopStatus::Waiting,
+        }
+    }
+
+    /// Whether auto-stop is paused. An expired pause is cleared.
+    fn is_paused(&self, now: Instant) -> bool {
+        let mut state = self.0.lock().unwrap();
+        if state.paused_until.is_some_and(|until| until <= now) {
+            state.paused = false;
+            state.paused_until = None;
+        }
+        state.paused
+    }
+
+    fn set_deadline(&self, deadline: Option<Instant>) {
+        self.0.lock().unwrap().deadline = deadline;
+    }
+}
+
+/// Events before the server is stopped automatically.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AutoStopEvent {
+    /// 残り時間の予告
+    Warn(Duration),
+    /// これから停止する
+    Stopping,
+    /// 停止前の処理の間にプレイヤーが参加したため、停止を中止した
+    Cancelled,
+}
+
+/// Stop the server when no player is in the registry for the idle time of the policy.
+///
+/// `on_event` is called in the watcher thread, and the server is stopped after `Stopping` returns
+/// unless a player joined in the meantime. The countdown is written to `state`,
+/// and it is not counted while `state` is paused.
+pub fn auto_stop_inspect(
+    stdin: Sender<String>,
+    policy: AutoStopPolicy,
+    stop_reason: StopReasonRecorder,
+    players: PlayerRegistry,
+    state: AutoStopState,
+    on_event: impl Fn(AutoStopEvent) + Send + 'static,
+) -> PlayerNotifier {
     use PlayerNotification::*;
 
     let (tx, rx) = channel();
 
     thread::spawn(move || {
-        let mut watching = false;
-        let mut players = 0i32;
+        // メッセージが送信された時点でサーバは開始されていると判断する
+        let mut started_at: Option<Instant> = None;
+        let mut idle_since: Option<Instant> = None;
+        let mut warned = false;
 
         loop {
-            match rx.recv_timeout(time::Duration::from_secs(sec)) {
+            match rx.recv_timeout(TICK) {
                 Ok(v) => {
-                    // メッセージが送信された時点でサーバは開始されていると判断する
-                    watching = true;
-                    match v {
-                        Join => players += 1,
-                        Leave => players -= 1,
-                        _ => {}
-                    };
-
-                    println!("There is/are {} players", players)
-                }
-                Err(err) => match err {
-                    Timeout => {
-                        if watching && players == 0 {
-                            println!("自動終了します……");
-                            stdin.send("stop".to_string()).ok();
-                            break;
-                        }
-                    }
-                    Disconnected => {
-                        break;
+                    started_at.get_or_insert_with(Instant::now);
+                    if let Join | Leave = v {
+                        println!("There is/are {} players", players.len());
                     }
-                },
+                }
+                Err(Timeout) => {}
+                Err(Disconnected) => break,
+            }
+
+            let Some(started_at) = started_at else {
+                continue;
+            };
+            let now = Instant::now();
+            if state.is_paused(now)
+                || !players.is_empty()
+                || policy.is_keep_running(Local::now().time())
+            {
+                idle_since = None;
+                warned = false;
+                state.set_deadline(None);
+                continue;
+            }
+
+            let since = *idle_since.get_or_insert(now);
+            let deadline = (since + policy.idle).max(started_at + policy.grace);
+            state.set_deadline(Some(deadline));
+
+            if now >= deadline {
+                println!("自動終了します……");
+                on_event(AutoStopEvent::Stopping);
+
+                // バックアップなどの間に参加したプレイヤーを追い出さない
+                if !players.is_empty() {
+                    println!("プレイヤーが参加したため、自動終了を中止しました。");
+                    on_event(AutoStopEvent::Cancelled);
+                    idle_since = None;
+                    warned = false;
+                    state.set_deadline(None);
+                    continue;
+                }
+
+                stop_reason.record(StopReason::AutoStop);
+                stdin.send("stop".to_string()).ok();
+                break;
+            }
+
+            let remaining = deadline - now;
+            if !warned && policy.warning.is_some_and(|warning| remaining <= warning) {
+                warned = true;
+                on_event(AutoStopEvent::Warn(remaining));
             }
         }
+
+        state.set_deadline(None);
     });
 
     PlayerNotifier(tx)
@@ -83,17 +240,53 @@ pub fn auto_stop_inspect(stdin: Sender<String>, sec: u64) -> PlayerNotifier {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::server::LogEvent;
     use std::sync::mpsc;
-    use std::time::Duration;
+
+    fn join(players: &PlayerRegistry, notifier: &PlayerNotifier) -> PlayerNotifierResult {
+        players.apply(&LogEvent::PlayerJoin {
+            name: "Steve".to_string(),
+        });
+        notifier.join()
+    }
+
+    fn leave(players: &PlayerRegistry, notifier: &PlayerNotifier) -> PlayerNotifierResult {
+        players.apply(&LogEvent::PlayerLeave {
+            name: "Steve".to_string(),
+            reason: None,
+        });
+        notifier.leave()
+    }
+
+    fn inspect(
+        tx: Sender<String>,
+        sec: u64,
+        stop_reason: StopReasonRecorder,
+        players: PlayerRegistry,
+    ) -> PlayerNotifier {
+        let policy = AutoStopPolicy {
+            idle: Duration::from_secs(sec),
+            ..Default::default()
+        };
+        auto_stop_inspect(
+            tx,
+            policy,
+            stop_reason,
+            players,
+            AutoStopState::default(),
+            |_| {},
+        )
+    }
 
     #[test]
     fn auto_stop_after_all_players_leaved() {
         let (tx, _) = mpsc::channel();
-        let r = auto_stop_inspect(tx, 2);
+        let players = PlayerRegistry::default();
+        let r = inspect(tx, 2, StopReasonRecorder::default(), players.clone());
 
-        r.join().unwrap();
+        join(&players, &r).unwrap();
         std::thread::sleep(Duration::from_secs(3));
-        r.leave().unwrap();
+        leave(&players, &r).unwrap();
         std::thread::sleep(Duration::from_secs(3));
         assert!(r.join().is_err());
     }
@@ -101,9 +294,10 @@ mod tests {
     #[test]
     fn do_not_stop_when_player_is_joining() {
         let (tx, _) = mpsc::channel();
-        let r = auto_stop_inspect(tx, 1);
+        let players = PlayerRegistry::default();
+        let r = inspect(tx, 1, StopReasonRecorder::default(), players.clone());
 
-        r.join().unwrap();
+        join(&players, &r).unwrap();
         std::thread::sleep(Duration::from_secs(2));
         assert!(r.join().is_ok());
     }
@@ -113,20 +307,213 @@ mod tests {
         let (tx, rx) = mpsc::channel();
 
         #[allow(unused_variables)]
-        let counter = auto_stop_inspect(tx, 1);
+        let counter = inspect(
+            tx,
+            1,
+            StopReasonRecorder::default(),
+            PlayerRegistry::default(),
+        );
         counter.start().unwrap();
 
         assert_eq!(rx.recv().unwrap(), "stop");
     }
 
+    #[test]
+    fn record_auto_stop_reason() {
+        let (tx, rx) = mpsc::channel();
+        let stop_reason = StopReasonRecorder::default();
+
+        let counter = inspect(tx, 1, stop_reason.clone(), PlayerRegistry::default());
+        counter.start().unwrap();
+
+        assert_eq!(rx.recv().unwrap(), "stop");
+        assert_eq!(stop_reason.take(), StopReason::AutoStop);
+    }
+
     #[test]
     fn not_stop_when_watching_disabled() {
         let (tx, _) = mpsc::channel();
 
         #[allow(unused_variables)]
-        let counter = auto_stop_inspect(tx, 1);
+        let counter = inspect(
+            tx,
+            1,
+            StopReasonRecorder::default(),
+            PlayerRegistry::default(),
+        );
         thread::sleep(Duration::from_secs(2));
 
         assert!(counter.join().is_ok());
     }
+
+    #[test]
+    fn do_not_stop_when_list_found_players() {
+        let (tx, _) = mpsc::channel();
+        let players = PlayerRegistry::default();
+        let counter = inspect(tx, 1, StopReasonRecorder::default(), players.clone());
+
+        // 参加のログを見逃していても、`list` で見つかれば停止しない
+        players.sync(&["Steve".to_string()]);
+        counter.start().unwrap();
+        thread::sleep(Duration::from_secs(2));
+
+        assert!(counter.join().is_ok());
+    }
+
+    #[test]
+    fn warn_before_auto_stop() {
+        let (tx, rx) = mpsc::channel();
+        let (event_tx, event_rx) = mpsc::channel();
+        let policy = AutoStopPolicy {
+            idle: Duration::from_secs(3),
+            warning: Some(Duration::from_secs(2)),
+            ..Default::default()
+        };
+
+        let counter = auto_stop_inspect(
+            tx,
+            policy,
+            StopReasonRecorder::default(),
+            PlayerRegistry::default(),
+            AutoStopState::default(),
+            move |event| event_tx.send(event).unwrap(),
+        );
+        counter.start().unwrap();
+
+        assert!(matches!(event_rx.recv().unwrap(), AutoStopEvent::Warn(_)));
+        assert_eq!(event_rx.recv().unwrap(), AutoStopEvent::Stopping);
+        assert_eq!(rx.recv().unwrap(), "stop");
+    }
+
+    #[test]
+    fn wait_for_grace_period() {
+        let (tx, rx) = mpsc::channel();
+        let policy = AutoStopPolicy {
+            idle: Duration::from_secs(1),
+            grace: Duration::from_secs(3),
+            ..Default::default()
+        };
+
+        let counter = auto_stop_inspect(
+            tx,
+            policy,
+            StopReasonRecorder::default(),
+            PlayerRegistry::default(),
+            AutoStopState::default(),
+            |_| {},
+        );
+        counter.start().unwrap();
+
+        assert!(rx.recv_timeout(Duration::from_secs(2)).is_err());
+        assert_eq!(rx.recv().unwrap(), "stop");
+    }
+
+    #[test]
+    fn keep_running_during_hours() {
+        let (tx, rx) = mpsc::channel();
+        let policy = AutoStopPolicy {
+            idle: Duration::from_secs(1),
+            // 終日
+            keep_running: vec![TimeRange::parse("00:00-00:00").unwrap()],
+            ..Default::default()
+        };
+
+        let counter = auto_stop_inspect(
+            tx,
+            policy,
+            StopReasonRecorder::default(),
+            PlayerRegistry::default(),
+            AutoStopState::default(),
+            |_| {},
+        );
+        counter.start().unwrap();
+
+        assert!(rx.recv_timeout(Duration::from_secs(3)).is_err());
+    }
+
+    #[test]
+    fn cancel_when_player_joins_before_stop() {
+        let (tx, rx) = mpsc::channel();
+        let (event_tx, event_rx) = mpsc::channel();
+        let players = PlayerRegistry::default();
+        let policy = AutoStopPolicy {
+            idle: Duration::from_secs(1),
+            ..Default::default()
+        };
+
+        let counter = {
+            let players = players.clone();
+            auto_stop_inspect(
+                tx,
+                policy,
+                StopReasonRecorder::default(),
+                players.clone(),
+                AutoStopState::default(),
+                move |event| {
+                    // 停止前のバックアップの間にプレイヤーが参加する
+                    if event == AutoStopEvent::Stopping {
+                        players.apply(&LogEvent::PlayerJoin {
+                            name: "Steve".to_string(),
+                        });
+                    }
+                    event_tx.send(event).unwrap();
+                },
+            )
+        };
+        counter.start().unwrap();
+
+        assert_eq!(event_rx.recv().unwrap(), AutoStopEvent::Stopping);
+        assert_eq!(event_rx.recv().unwrap(), AutoStopEvent::Cancelled);
+        assert!(rx.recv_timeout(Duration::from_secs(2)).is_err());
+        assert_eq!(players.len(), 1);
+    }
+
+    #[test]
+    fn reset_pause() {
+        let state = AutoStopState::default();
+        state.pause(None);
+
+        state.reset();
+        assert_eq!(state.status(), AutoStopStatus::Waiting);
+    }
+
+    #[test]
+    fn pause_and_resume() {
+        let (tx, rx) = mpsc::channel();
+        let state = AutoStopState::default();
+        let policy = AutoStopPolicy {
+            idle: Duration::from_secs(2),
+            ..Default::default()
+        };
+
+        state.pause(None);
+        let counter = auto_stop_inspect(
+            tx,
+            policy,
+            StopReasonRecorder::default(),
+            PlayerRegistry::default(),
+            state.clone(),
+            |_| {},
+        );
+        counter.start().unwrap();
+
+        assert!(rx.recv_timeout(Duration::from_secs(3)).is_err());
+        assert_eq!(state.status(), AutoStopStatus::Paused(None));
+
+        state.resume();
+        thread::sleep(Duration::from_millis(1500));
+        assert!(matches!(state.status(), AutoStopStatus::Countdown(_)));
+        assert_eq!(rx.recv().unwrap(), "stop");
+    }
+
+    #[test]
+    fn pause_expires() {
+        let state = AutoStopState::default();
+
+        state.pause(Some(Duration::from_secs(60)));
+        assert!(matches!(state.status(), AutoStopStatus::Paused(Some(_))));
+
+        state.pause(Some(Duration::ZERO));
+        assert_eq!(state.status(), AutoStopStatus::Waiting);
+    }
 }
@@ -0,0 +1,58 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+/// Find the newest crash report in `<work_dir>/crash-reports` written after `since`.
+pub fn latest_crash_report(work_dir: &Path, since: SystemTime) -> Option<PathBuf> {
+    let entries = fs::read_dir(work_dir.join("crash-reports")).ok()?;
+
+    entries
+        .filter_map(|entry| {
+            let entry = entry.ok()?;
+            let modified = entry.metadata().ok()?.modified().ok()?;
+            (entry.path().is_file() && modified >= since).then(|| (modified, entry.path()))
+        })
+        .max_by_key(|(modified, _)| *modified)
+        .map(|(_, path)| path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn temp_work_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("hone-test-{}-{}", name, std::process::id()));
+        fs::remove_dir_all(&dir).ok();
+        fs::create_dir_all(dir.join("crash-reports")).unwrap();
+        dir
+    }
+
+    #[test]
+    fn find_newest_crash_report() {
+        let dir = temp_work_dir("crash-report-newest");
+        let since = SystemTime::now() - Duration::from_secs(1);
+
+        fs::write(dir.join("crash-reports/crash-1.txt"), "old").unwrap();
+        std::thread::sleep(Duration::from_millis(20));
+        fs::write(dir.join("crash-reports/crash-2.txt"), "new").unwrap();
+
+        let report = latest_crash_report(&dir, since).unwrap();
+        assert_eq!(report.file_name().unwrap(), "crash-2.txt");
+
+        fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn ignore_crash_reports_before_start() {
+        let dir = temp_work_dir("crash-report-old");
+        fs::write(dir.join("crash-reports/crash-1.txt"), "old").unwrap();
+
+        let since = SystemTime::now() + Duration::from_secs(60);
+        assert!(latest_crash_report(&dir, since).is_none());
+
+        fs::remove_dir_all(&dir).ok();
+    }
+}
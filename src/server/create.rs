@@ -6,17 +6,52 @@ pub use self::not_windows::*;
 
 #[cfg(target_os = "windows")]
 mod windows {
+    use std::ffi::OsStr;
+    use std::io;
+    use std::os::windows::process::CommandExt;
+    use std::path::Path;
     use std::process::Command;
 
-    pub fn command_new() -> Command {
-        let mut cmd = Command::new("cmd");
-        cmd.arg("/C");
+    /// `CREATE_NEW_PROCESS_GROUP`
+    const CREATE_NEW_PROCESS_GROUP: u32 = 0x0000_0200;
+
+    pub fn command_new(program: impl AsRef<OsStr>) -> Command {
+        let mut cmd = Command::new(program);
+        // コンソールのCtrl+Cがサーバに直接届かないように、別のプロセスグループで起動する。
+        cmd.creation_flags(CREATE_NEW_PROCESS_GROUP);
         cmd
     }
 
+    /// Create a command which runs the launch script with `cmd`.
+    pub fn script_command_new(script: &Path) -> Command {
+        let mut cmd = command_new("cmd");
+        cmd.arg("/C").arg(script);
+        cmd
+    }
+
+    /// Ask the process and its children to exit.
+    pub fn terminate_process(pid: u32) -> io::Result<()> {
+        run_taskkill(&["/PID", &pid.to_string(), "/T"])
+    }
+
+    /// Kill the process and its children forcibly.
+    pub fn kill_process(pid: u32) -> io::Result<()> {
+        run_taskkill(&["/PID", &pid.to_string(), "/T", "/F"])
+    }
+
+    fn run_taskkill(args: &[&str]) -> io::Result<()> {
+        let status = Command::new("taskkill").args(args).status()?;
+        if !status.success() {
+            return Err(io::Error::other(format!(
+                "taskkill が失敗しました: {status}"
+            )));
+        }
+        Ok(())
+    }
+
     fn firewall_process_new() -> Command {
-        let mut cmd = command_new();
-        cmd.arg("netsh").arg("advfirewall").arg("firewall");
+        let mut cmd = Command::new("netsh");
+        cmd.arg("advfirewall").arg("firewall");
         cmd
     }
 
@@ -56,11 +91,47 @@ mod windows {
 
 #[cfg(not(target_os = "windows"))]
 mod not_windows {
+    use std::ffi::OsStr;
+    use std::io;
+    use std::os::unix::process::CommandExt;
+    use std::path::Path;
     use std::process::Command;
 
-    pub fn command_new() -> Command {
-        let mut cmd = Command::new("sh");
-        cmd.arg("-c");
+    pub fn command_new(program: impl AsRef<OsStr>) -> Command {
+        let mut cmd = Command::new(program);
+        // 端末からのSIGINTがサーバに直接届かないように、別のプロセスグループで起動する。
+        // 終了はhoneが`stop`を送信して行う。
+        cmd.process_group(0);
         cmd
     }
+
+    /// Create a command which runs the launch script with `sh`.
+    pub fn script_command_new(script: &Path) -> Command {
+        let mut cmd = command_new("sh");
+        cmd.arg(script);
+        cmd
+    }
+
+    /// Send `SIGTERM` to the process group of the server.
+    pub fn terminate_process(pid: u32) -> io::Result<()> {
+        send_signal(pid, "TERM")
+    }
+
+    /// Send `SIGKILL` to the process group of the server.
+    pub fn kill_process(pid: u32) -> io::Result<()> {
+        send_signal(pid, "KILL")
+    }
+
+    fn send_signal(pid: u32, signal: &str) -> io::Result<()> {
+        // サーバは自身のプロセスグループで起動しているので、起動スクリプトの子プロセスにも届く
+        let status = Command::new("kill")
+            .arg(format!("-{signal}"))
+            .arg("--")
+            .arg(format!("-{pid}"))
+            .status()?;
+        if !status.success() {
+            return Err(io::Error::other(format!("kill が失敗しました: {status}")));
+        }
+        Ok(())
+    }
 }
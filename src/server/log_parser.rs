@@ -0,0 +1,605 @@
+//! Parser for the vanilla/Paper/Forge server log format.
+//!
+//! Supported line formats:
+//!
+//! - vanilla: `[12:34:56] [Server thread/INFO]: message`
+//! - Paper:   `[12:34:56 INFO]: message`
+//! - Forge:   `[12:34:56] [Server thread/INFO] [minecraft/DedicatedServer]: message`
+use std::collections::{HashMap, HashSet};
+
+/// ログレベル
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LogLevel {
+    Info,
+    Warn,
+    Error,
+    Other,
+}
+
+impl LogLevel {
+    fn parse(s: &str) -> LogLevel {
+        match s {
+            "INFO" => LogLevel::Info,
+            "WARN" => LogLevel::Warn,
+            "ERROR" | "FATAL" => LogLevel::Error,
+            _ => LogLevel::Other,
+        }
+    }
+}
+
+/// A single line of the server log.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LogLine {
+    pub time: String,
+    pub thread: Option<String>,
+    pub level: LogLevel,
+    pub message: String,
+}
+
+impl LogLine {
+    /// Parse a line. Returns `None` if the line is not in the log format
+    /// (e.g. stack traces or output printed directly by mods).
+    pub fn parse(line: &str) -> Option<LogLine> {
+        let line = line.trim_end_matches(['\r', '\n']);
+
+        // [time] or [time LEVEL]
+        let rest = line.strip_prefix('[')?;
+        let (head, rest) = rest.split_once(']')?;
+        let (time, paper_level) = match head.split_once(' ') {
+            Some((time, level)) => (time, Some(level)),
+            None => (head, None),
+        };
+        if !is_time(time) {
+            return None;
+        }
+
+        let (thread, level, rest) = match paper_level {
+            Some(level) => (None, level, rest),
+            None => {
+                // [thread/LEVEL]
+                let rest = rest.strip_prefix(" [")?;
+                let (thread_level, rest) = rest.split_once(']')?;
+                let (thread, level) = thread_level.rsplit_once('/')?;
+                (Some(thread.to_string()), level, rest)
+            }
+        };
+
+        // Forge形式のロガー名 [logger] を読み飛ばす
+        let rest = match rest.strip_prefix(" [") {
+            Some(rest) => rest.split_once(']')?.1,
+            None => rest,
+        };
+        let message = rest.strip_prefix(": ")?;
+
+        Some(LogLine {
+            time: time.to_string(),
+            thread,
+            level: LogLevel::parse(level),
+            message: message.to_string(),
+        })
+    }
+
+    /// Whether the server finished starting up.
+    pub fn is_done(&self) -> bool {
+        self.level == LogLevel::Info
+            && self.message.starts_with("Done (")
+            && self.message.contains(")! For help, type")
+    }
+
+    /// Whether the server requires agreeing to the EULA.
+    pub fn is_eula_required(&self) -> bool {
+        self.message
+            .starts_with("You need to agree to the EULA in order to run the server")
+    }
+
+    /// Whether the server saved the worlds by `save-all`.
+    pub fn is_game_saved(&self) -> bool {
+        self.level == LogLevel::Info && self.message == "Saved the game"
+    }
+
+    /// Whether the server saved all worlds while stopping.
+    pub fn is_all_saved(&self) -> bool {
+        // チャットの `<Steve> All dimensions are saved` などと区別するため、メッセージ全体を比較する
+        self.level == LogLevel::Info
+            && matches!(
+                self.message.as_str(),
+                "ThreadedAnvilChunkStorage: All dimensions are saved" | "All dimensions are saved"
+            )
+    }
+}
+
+/// An event parsed from the server log.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum LogEvent {
+    PlayerJoin {
+        name: String,
+    },
+    PlayerLeave {
+        name: String,
+        reason: Option<String>,
+    },
+    Chat {
+        name: String,
+        text: String,
+    },
+    Death {
+        name: String,
+        message: String,
+    },
+    Advancement {
+        name: String,
+        advancement: String,
+    },
+    /// プレイヤーの認証が完了した
+    PlayerUuid {
+        name: String,
+        uuid: String,
+    },
+    /// プレイヤーがログインした。参加する直前に出力される
+    PlayerLogin {
+        name: String,
+        address: String,
+    },
+    /// `list` コマンドの出力
+    PlayerList {
+        names: Vec<String>,
+    },
+    Warn(String),
+    Error(String),
+}
+
+/// Death messages begin with the player's name followed by one of them.
+const DEATH_PHRASES: &[&str] = &[
+    "was ",
+    "walked into ",
+    "drowned",
+    "died",
+    "experienced kinetic energy",
+    "blew up",
+    "hit the ground too hard",
+    "fell ",
+    "went up in flames",
+    "went off with a bang",
+    "burned to death",
+    "tried to swim in lava",
+    "suffocated",
+    "starved to death",
+    "froze to death",
+    "withered away",
+    "discovered the floor was lava",
+    "didn't want to live",
+    "left the confines of this world",
+];
+
+const ADVANCEMENT_PHRASES: &[&str] = &[
+    " has made the advancement [",
+    " has completed the challenge [",
+    " has reached the goal [",
+];
+
+/// Stateful log parser.
+///
+/// It remembers the players online, so that death messages can be told apart from other messages.
+#[derive(Default)]
+pub struct LogParser {
+    online: HashSet<String>,
+    leave_reasons: HashMap<String, String>,
+}
+
+impl LogParser {
+    pub fn new() -> LogParser {
+        LogParser::default()
+    }
+
+    /// Parse a log line into an event.
+    pub fn parse(&mut self, line: &LogLine) -> Option<LogEvent> {
+        let message = line.message.as_str();
+
+        match line.level {
+            LogLevel::Info => {}
+            LogLevel::Warn => return Some(LogEvent::Warn(message.to_string())),
+            LogLevel::Error => return Some(LogEvent::Error(message.to_string())),
+            LogLevel::Other => return None,
+        }
+
+        // チャットは `<name> text`。署名されていないメッセージには `[Not Secure] ` が付く
+        let chat = message.strip_prefix("[Not Secure] ").unwrap_or(message);
+        if let Some((name, text)) = chat
+            .strip_prefix('<')
+            .and_then(|rest| rest.split_once("> "))
+        {
+            if is_player_name(name) {
+                return Some(LogEvent::Chat {
+                    name: name.to_string(),
+                    text: text.to_string(),
+                });
+            }
+        }
+
+        if let Some(name) = message.strip_suffix(" joined the game") {
+            if is_player_name(name) {
+                self.online.insert(name.to_string());
+                return Some(LogEvent::PlayerJoin {
+                    name: name.to_string(),
+                });
+            }
+        }
+
+        if let Some(name) = message.strip_suffix(" left the game") {
+            if is_player_name(name) {
+                self.online.remove(name);
+                return Some(LogEvent::PlayerLeave {
+                    name: name.to_string(),
+                    reason: self.leave_reasons.remove(name),
+                });
+            }
+        }
+
+        if let Some((name, uuid)) = message
+            .strip_prefix("UUID of player ")
+            .and_then(|rest| rest.split_once(" is "))
+        {
+            if is_player_name(name) {
+                return Some(LogEvent::PlayerUuid {
+                    name: name.to_string(),
+                    uuid: uuid.to_string(),
+                });
+            }
+        }
+
+        // `Steve[/127.0.0.1:54321] logged in with entity id 123 at (...)`
+        if let Some((name, rest)) = message
+            .split_once(" logged in with entity id ")
+            .and_then(|(head, _)| head.split_once("[/"))
+        {
+            if is_player_name(name) {
+                let address = rest.trim_end_matches(']');
+                // ポート番号を取り除く
+                let address = address.rsplit_once(':').map_or(address, |(host, _)| host);
+                return Some(LogEvent::PlayerLogin {
+                    name: name.to_string(),
+                    address: address.trim_matches(['[', ']']).to_string(),
+                });
+            }
+        }
+
+        if let Some(names) = parse_player_list(message) {
+            self.online = names.iter().cloned().collect();
+            return Some(LogEvent::PlayerList { names });
+        }
+
+        if let Some((name, reason)) = message.split_once(" lost connection: ") {
+            if is_player_name(name) {
+                self.leave_reasons
+                    .insert(name.to_string(), reason.to_string());
+                return None;
+            }
+        }
+
+        for phrase in ADVANCEMENT_PHRASES {
+            if let Some((name, rest)) = message.split_once(phrase) {
+                if is_player_name(name) {
+                    return Some(LogEvent::Advancement {
+                        name: name.to_string(),
+                        advancement: rest.trim_end_matches(']').to_string(),
+                    });
+                }
+            }
+        }
+
+        // 死亡メッセージはプレイヤー名から始まるので、オンラインのプレイヤーに限って判定する
+        let (name, rest) = message.split_once(' ')?;
+        if self.online.contains(name) && DEATH_PHRASES.iter().any(|p| rest.starts_with(p)) {
+            return Some(LogEvent::Death {
+                name: name.to_string(),
+                message: message.to_string(),
+            });
+        }
+
+        None
+    }
+}
+
+/// Parse the output of `list`, e.g. `There are 2 of a max of 20 players online: Steve, Alex`.
+///
+/// The UUIDs printed by `list uuids` are removed.
+pub fn parse_player_list(output: &str) -> Option<Vec<String>> {
+    let rest = output.trim().strip_prefix("There are ")?;
+    let (counts, names) = rest.split_once(" players online:")?;
+    if !counts.contains(" of a max of ") {
+        return None;
+    }
+
+    let names = names
+        .split(',')
+        .map(|name| name.split(" (").next().unwrap_or_default().trim())
+        .filter(|name| is_player_name(name))
+        .map(str::to_string)
+        .collect();
+    Some(names)
+}
+
+/// `HH:MM:SS`
+fn is_time(s: &str) -> bool {
+    s.len() == 8
+        && s.char_indices().all(|(i, c)| match i {
+            2 | 5 => c == ':',
+            _ => c.is_ascii_digit(),
+        })
+}
+
+/// Java版のプレイヤー名 (Geyser経由のBedrock版プレイヤーは `.` から始まる)
+fn is_player_name(s: &str) -> bool {
+    let s = s.strip_prefix('.').unwrap_or(s);
+    (1..=16).contains(&s.len()) && s.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn event(parser: &mut LogParser, line: &str) -> Option<LogEvent> {
+        parser.parse(&LogLine::parse(line).unwrap())
+    }
+
+    #[test]
+    fn parse_vanilla_line() {
+        let line =
+            LogLine::parse("[12:34:56] [Server thread/INFO]: Starting minecraft server\n").unwrap();
+
+        assert_eq!(line.time, "12:34:56");
+        assert_eq!(line.thread.as_deref(), Some("Server thread"));
+        assert_eq!(line.level, LogLevel::Info);
+        assert_eq!(line.message, "Starting minecraft server");
+    }
+
+    #[test]
+    fn parse_paper_and_forge_line() {
+        let paper = LogLine::parse("[12:34:56 WARN]: Can't keep up!").unwrap();
+        assert_eq!(paper.thread, None);
+        assert_eq!(paper.level, LogLevel::Warn);
+        assert_eq!(paper.message, "Can't keep up!");
+
+        let forge =
+            LogLine::parse("[12:34:56] [Server thread/INFO] [minecraft/DedicatedServer]: Done (1.0s)! For help, type \"help\"")
+                .unwrap();
+        assert!(forge.is_done());
+    }
+
+    #[test]
+    fn ignore_non_log_line() {
+        assert!(LogLine::parse("\tat java.base/java.lang.Thread.run(Thread.java:833)").is_none());
+        assert!(LogLine::parse("[not a time] [Server thread/INFO]: hi").is_none());
+    }
+
+    #[test]
+    fn detect_lifecycle() {
+        let done = LogLine::parse(
+            "[12:34:56] [Server thread/INFO]: Done (3.21s)! For help, type \"help\"",
+        )
+        .unwrap();
+        assert!(done.is_done());
+
+        let saved = LogLine::parse(
+            "[12:34:56] [Server thread/INFO]: ThreadedAnvilChunkStorage: All dimensions are saved",
+        )
+        .unwrap();
+        assert!(saved.is_all_saved());
+
+        let saved = LogLine::parse("[12:34:56] [Server thread/INFO]: Saved the game").unwrap();
+        assert!(saved.is_game_saved());
+    }
+
+    #[test]
+    fn chat_is_not_all_saved() {
+        let chat = LogLine::parse(
+            "[12:34:56] [Server thread/INFO]: <Steve> ThreadedAnvilChunkStorage: All dimensions are saved",
+        )
+        .unwrap();
+        assert!(!chat.is_all_saved());
+
+        let say =
+            LogLine::parse("[12:34:56] [Server thread/INFO]: [Steve] All dimensions are saved")
+                .unwrap();
+        assert!(!say.is_all_saved());
+    }
+
+    #[test]
+    fn player_named_done_is_not_lifecycle() {
+        let mut parser = LogParser::new();
+
+        let join = LogLine::parse("[12:34:56] [Server thread/INFO]: Done joined the game").unwrap();
+        assert!(!join.is_done());
+        assert_eq!(
+            parser.parse(&join),
+            Some(LogEvent::PlayerJoin {
+                name: "Done".to_string()
+            })
+        );
+
+        let chat = LogLine::parse(
+            "[12:34:56] [Server thread/INFO]: <Done> Done (1.0s)! For help, type \"help\"",
+        )
+        .unwrap();
+        assert!(!chat.is_done());
+    }
+
+    #[test]
+    fn chat_is_not_join() {
+        let mut parser = LogParser::new();
+
+        assert_eq!(
+            event(
+                &mut parser,
+                "[12:34:56] [Server thread/INFO]: <Steve> Alex joined the game"
+            ),
+            Some(LogEvent::Chat {
+                name: "Steve".to_string(),
+                text: "Alex joined the game".to_string()
+            })
+        );
+        assert_eq!(
+            event(
+                &mut parser,
+                "[12:34:56] [Server thread/INFO]: [Not Secure] <Steve> hello"
+            ),
+            Some(LogEvent::Chat {
+                name: "Steve".to_string(),
+                text: "hello".to_string()
+            })
+        );
+    }
+
+    #[test]
+    fn leave_with_reason() {
+        let mut parser = LogParser::new();
+
+        assert_eq!(
+            event(
+                &mut parser,
+                "[12:34:56] [Server thread/INFO]: Steve lost connection: Disconnected"
+            ),
+            None
+        );
+        assert_eq!(
+            event(
+                &mut parser,
+                "[12:34:56] [Server thread/INFO]: Steve left the game"
+            ),
+            Some(LogEvent::PlayerLeave {
+                name: "Steve".to_string(),
+                reason: Some("Disconnected".to_string())
+            })
+        );
+    }
+
+    #[test]
+    fn death_and_advancement() {
+        let mut parser = LogParser::new();
+        event(
+            &mut parser,
+            "[12:34:56] [Server thread/INFO]: Steve joined the game",
+        );
+
+        assert_eq!(
+            event(
+                &mut parser,
+                "[12:34:56] [Server thread/INFO]: Steve was slain by Zombie"
+            ),
+            Some(LogEvent::Death {
+                name: "Steve".to_string(),
+                message: "Steve was slain by Zombie".to_string()
+            })
+        );
+        assert_eq!(
+            event(
+                &mut parser,
+                "[12:34:56] [Server thread/INFO]: Steve has made the advancement [Stone Age]"
+            ),
+            Some(LogEvent::Advancement {
+                name: "Steve".to_string(),
+                advancement: "Stone Age".to_string()
+            })
+        );
+
+        // オンラインでないプレイヤーの死亡メッセージとは判定しない
+        assert_eq!(
+            event(
+                &mut parser,
+                "[12:34:56] [Server thread/INFO]: Alex was here"
+            ),
+            None
+        );
+    }
+
+    #[test]
+    fn keep_log_level() {
+        let mut parser = LogParser::new();
+
+        assert_eq!(
+            event(
+                &mut parser,
+                "[12:34:56] [Server thread/WARN]: Can't keep up!"
+            ),
+            Some(LogEvent::Warn("Can't keep up!".to_string()))
+        );
+        assert_eq!(
+            event(
+                &mut parser,
+                "[12:34:56] [Server thread/ERROR]: Encountered an unexpected exception"
+            ),
+            Some(LogEvent::Error(
+                "Encountered an unexpected exception".to_string()
+            ))
+        );
+    }
+
+    #[test]
+    fn parse_player_details() {
+        let mut parser = LogParser::new();
+
+        assert_eq!(
+            event(
+                &mut parser,
+                "[12:34:56] [User Authenticator #1/INFO]: UUID of player Steve is 069a79f4-44e9-4726-a5be-fca90e38aaf5"
+            ),
+            Some(LogEvent::PlayerUuid {
+                name: "Steve".to_string(),
+                uuid: "069a79f4-44e9-4726-a5be-fca90e38aaf5".to_string()
+            })
+        );
+        assert_eq!(
+            event(
+                &mut parser,
+                "[12:34:56] [Server thread/INFO]: Steve[/192.168.0.2:54321] logged in with entity id 123 at (0.5, 64.0, 0.5)"
+            ),
+            Some(LogEvent::PlayerLogin {
+                name: "Steve".to_string(),
+                address: "192.168.0.2".to_string()
+            })
+        );
+        assert_eq!(
+            event(
+                &mut parser,
+                "[12:34:56] [Server thread/INFO]: Alex[/[0:0:0:0:0:0:0:1]:54321] logged in with entity id 124 at (0.5, 64.0, 0.5)"
+            ),
+            Some(LogEvent::PlayerLogin {
+                name: "Alex".to_string(),
+                address: "0:0:0:0:0:0:0:1".to_string()
+            })
+        );
+    }
+
+    #[test]
+    fn parse_list_output() {
+        assert_eq!(
+            parse_player_list("There are 2 of a max of 20 players online: Steve, Alex"),
+            Some(vec!["Steve".to_string(), "Alex".to_string()])
+        );
+        assert_eq!(
+            parse_player_list("There are 0 of a max of 20 players online: "),
+            Some(vec![])
+        );
+        assert_eq!(
+            parse_player_list(
+                "There are 1 of a max of 20 players online: Steve (069a79f4-44e9-4726-a5be-fca90e38aaf5)"
+            ),
+            Some(vec!["Steve".to_string()])
+        );
+        assert_eq!(parse_player_list("There are no players"), None);
+
+        // `list` で見つけたプレイヤーの死亡メッセージも判定する
+        let mut parser = LogParser::new();
+        event(
+            &mut parser,
+            "[12:34:56] [Server thread/INFO]: There are 1 of a max of 20 players online: Steve",
+        );
+        assert!(matches!(
+            event(
+                &mut parser,
+                "[12:34:56] [Server thread/INFO]: Steve drowned"
+            ),
+            Some(LogEvent::Death { .. })
+        ));
+    }
+}
@@ -0,0 +1,259 @@
+//! Registry of the players online, filled from the server log.
+use super::LogEvent;
+use chrono::{DateTime, Local};
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        mpsc::{channel, RecvTimeoutError, Sender},
+        Arc, Mutex,
+    },
+    thread,
+    time::Duration,
+};
+
+/// A player online.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct OnlinePlayer {
+    pub name: String,
+    pub uuid: Option<String>,
+    /// 接続元のIPアドレス
+    pub address: Option<String>,
+    /// 参加した日時。`list` で見つけた場合は見つけた日時
+    pub since: DateTime<Local>,
+}
+
+/// 参加する前にログに出力される情報
+#[derive(Default)]
+struct PendingPlayer {
+    uuid: Option<String>,
+    address: Option<String>,
+}
+
+#[derive(Default)]
+struct Players {
+    /// 参加した順
+    online: Vec<OnlinePlayer>,
+    pending: HashMap<String, PendingPlayer>,
+}
+
+impl Players {
+    fn join(&mut self, name: &str) {
+        let pending = self.pending.remove(name).unwrap_or_default();
+        self.leave(name);
+        self.online.push(OnlinePlayer {
+            name: name.to_string(),
+            uuid: pending.uuid,
+            address: pending.address,
+            since: Local::now(),
+        });
+    }
+
+    fn leave(&mut self, name: &str) {
+        self.online.retain(|player| player.name != name);
+    }
+
+    fn contains(&self, name: &str) -> bool {
+        self.online.iter().any(|player| player.name == name)
+    }
+}
+
+/// Players online, keyed by the player name.
+///
+/// It is updated from the log events, and re-synced from the output of `list`.
+#[derive(Clone, Default)]
+pub struct PlayerRegistry(Arc<Mutex<Players>>);
+
+impl PlayerRegistry {
+    /// Update the registry from a log event.
+    pub fn apply(&self, event: &LogEvent) {
+        let mut players = self.0.lock().unwrap();
+
+        match event {
+            LogEvent::PlayerUuid { name, uuid } => {
+                players.pending.entry(name.clone()).or_default().uuid = Some(uuid.clone());
+            }
+            LogEvent::PlayerLogin { name, address } => {
+                players.pending.entry(name.clone()).or_default().address = Some(address.clone());
+            }
+            LogEvent::PlayerJoin { name } => players.join(name),
+            LogEvent::PlayerLeave { name, .. } => {
+                players.leave(name);
+                players.pending.remove(name);
+            }
+            LogEvent::PlayerList { names } => {
+                drop(players);
+                self.sync(names);
+            }
+            _ => {}
+        }
+    }
+
+    /// Replace the players online with the output of `list`.
+    pub fn sync(&self, names: &[String]) {
+        let mut players = self.0.lock().unwrap();
+
+        players.online.retain(|player| names.contains(&player.name));
+        for name in names {
+            if !players.contains(name) {
+                players.join(name);
+            }
+        }
+    }
+
+    /// Get the players online in the order they joined.
+    pub fn online(&self) -> Vec<OnlinePlayer> {
+        self.0.lock().unwrap().online.clone()
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.lock().unwrap().online.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Forget all players, e.g. when the server stopped.
+    pub fn clear(&self) {
+        let mut players = self.0.lock().unwrap();
+        players.online.clear();
+        players.pending.clear();
+    }
+}
+
+/// Sends `list` to the server periodically to re-sync the registry.
+///
+/// Stops when dropped.
+pub struct PlayerSync {
+    _stop: Sender<()>,
+    /// 送信した `list` のうち、まだ出力を受け取っていない数
+    pending: Arc<AtomicUsize>,
+}
+
+impl PlayerSync {
+    /// Send `list` now and then every `interval`.
+    pub fn start(stdin: Sender<String>, interval: Duration) -> PlayerSync {
+        let (tx, rx) = channel::<()>();
+        let pending = Arc::new(AtomicUsize::new(0));
+
+        {
+            let pending = Arc::clone(&pending);
+            thread::spawn(move || loop {
+                pending.fetch_add(1, Ordering::SeqCst);
+                if stdin.send("list".to_string()).is_err() {
+                    break;
+                }
+
+                match rx.recv_timeout(interval) {
+                    Err(RecvTimeoutError::Timeout) => continue,
+                    _ => break,
+                }
+            });
+        }
+
+        PlayerSync { _stop: tx, pending }
+    }
+
+    /// Call when the output of `list` is received.
+    /// Returns whether it is the reply to `list` sent by this, not by a user.
+    pub fn take_reply(&self) -> bool {
+        self.pending
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| n.checked_sub(1))
+            .is_ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc;
+
+    fn join(name: &str) -> LogEvent {
+        LogEvent::PlayerJoin {
+            name: name.to_string(),
+        }
+    }
+
+    #[test]
+    fn track_players_from_events() {
+        let registry = PlayerRegistry::default();
+
+        registry.apply(&LogEvent::PlayerUuid {
+            name: "Steve".to_string(),
+            uuid: "069a79f4-44e9-4726-a5be-fca90e38aaf5".to_string(),
+        });
+        registry.apply(&LogEvent::PlayerLogin {
+            name: "Steve".to_string(),
+            address: "192.168.0.2".to_string(),
+        });
+        registry.apply(&join("Steve"));
+        registry.apply(&join("Alex"));
+
+        let online = registry.online();
+        assert_eq!(online.len(), 2);
+        assert_eq!(online[0].name, "Steve");
+        assert_eq!(online[0].address.as_deref(), Some("192.168.0.2"));
+        assert!(online[0].uuid.is_some());
+        assert_eq!(online[1].address, None);
+
+        registry.apply(&LogEvent::PlayerLeave {
+            name: "Steve".to_string(),
+            reason: None,
+        });
+        // 参加していないプレイヤーの退出では減らない
+        registry.apply(&LogEvent::PlayerLeave {
+            name: "Herobrine".to_string(),
+            reason: None,
+        });
+        assert_eq!(registry.len(), 1);
+    }
+
+    #[test]
+    fn resync_from_list() {
+        let registry = PlayerRegistry::default();
+        registry.apply(&join("Steve"));
+        registry.apply(&join("Alex"));
+        let since = registry.online()[0].since;
+
+        // honeが見逃した参加と退出を反映する
+        registry.apply(&LogEvent::PlayerList {
+            names: vec!["Steve".to_string(), "Notch".to_string()],
+        });
+
+        let online = registry.online();
+        let names: Vec<&str> = online.iter().map(|p| p.name.as_str()).collect();
+        assert_eq!(names, vec!["Steve", "Notch"]);
+        // 参加中のプレイヤーの参加日時は変わらない
+        assert_eq!(online[0].since, since);
+
+        registry.clear();
+        assert!(registry.is_empty());
+    }
+
+    #[test]
+    fn send_list_periodically() {
+        let (tx, rx) = mpsc::channel();
+        let sync = PlayerSync::start(tx, Duration::from_millis(100));
+
+        assert_eq!(rx.recv().unwrap(), "list");
+        assert_eq!(rx.recv().unwrap(), "list");
+
+        assert!(sync.take_reply());
+        drop(sync);
+        // 停止したらスレッドが送信側を手放す
+        while rx.recv_timeout(Duration::from_secs(1)).is_ok() {}
+        assert!(rx.recv().is_err());
+    }
+
+    #[test]
+    fn count_replies_to_own_list() {
+        let (tx, rx) = mpsc::channel();
+        let sync = PlayerSync::start(tx, Duration::from_secs(3600));
+
+        assert_eq!(rx.recv().unwrap(), "list");
+        assert!(sync.take_reply());
+        // ユーザが送信した `list` の出力
+        assert!(!sync.take_reply());
+    }
+}
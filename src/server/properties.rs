@@ -0,0 +1,103 @@
+use std::{collections::HashMap, fs, io, path::Path};
+
+/// Contents of `server.properties`.
+#[derive(Default)]
+pub struct ServerProperties(HashMap<String, String>);
+
+impl ServerProperties {
+    /// Read `server.properties` in `work_dir`.
+    pub fn read_from(work_dir: &Path) -> io::Result<ServerProperties> {
+        let content = fs::read_to_string(work_dir.join("server.properties"))?;
+        Ok(Self::parse(&content))
+    }
+
+    pub fn parse(content: &str) -> ServerProperties {
+        let properties = content
+            .lines()
+            .map(str::trim_start)
+            .filter(|line| !line.is_empty() && !line.starts_with('#') && !line.starts_with('!'))
+            .filter_map(|line| {
+                let (key, value) = line.split_once(['=', ':'])?;
+                Some((key.trim().to_string(), unescape(value.trim())))
+            })
+            .collect();
+
+        ServerProperties(properties)
+    }
+
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.0.get(key).map(String::as_str)
+    }
+
+    /// ワールドのディレクトリ名。設定されていなければ `world`
+    pub fn level_name(&self) -> &str {
+        self.get("level-name")
+            .filter(|name| !name.is_empty())
+            .unwrap_or("world")
+    }
+}
+
+/// Read the level name from `server.properties`, or `world` if it cannot be read.
+pub fn level_name(work_dir: &Path) -> String {
+    ServerProperties::read_from(work_dir)
+        .unwrap_or_default()
+        .level_name()
+        .to_string()
+}
+
+/// Unescape `\:` `\=` `\\` and so on. `\uXXXX` is also supported.
+fn unescape(value: &str) -> String {
+    let mut result = String::with_capacity(value.len());
+    let mut chars = value.chars();
+
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            result.push(c);
+            continue;
+        }
+
+        match chars.next() {
+            Some('t') => result.push('\t'),
+            Some('n') => result.push('\n'),
+            Some('r') => result.push('\r'),
+            Some('u') => {
+                let code: String = chars.by_ref().take(4).collect();
+                if let Some(c) = u32::from_str_radix(&code, 16).ok().and_then(char::from_u32) {
+                    result.push(c);
+                }
+            }
+            Some(c) => result.push(c),
+            None => {}
+        }
+    }
+
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_properties() {
+        let properties = ServerProperties::parse(
+            "#Minecraft server properties\n\
+             level-name=my world\n\
+             motd=A Minecraft Server\\: \\u3042\n\
+             server-port=25565\n",
+        );
+
+        assert_eq!(properties.level_name(), "my world");
+        assert_eq!(properties.get("motd"), Some("A Minecraft Server: あ"));
+        assert_eq!(properties.get("server-port"), Some("25565"));
+    }
+
+    #[test]
+    fn default_level_name() {
+        assert_eq!(
+            ServerProperties::parse("level-name=\n").level_name(),
+            "world"
+        );
+        assert_eq!(ServerProperties::default().level_name(), "world");
+    }
+}
@@ -0,0 +1,128 @@
+//! Primitives of the Minecraft Java Edition network protocol.
+//!
+//! <https://minecraft.wiki/w/Java_Edition_protocol/Data_types>
+use std::io::{self, Read, Write};
+
+/// VarIntの最大バイト数
+const VARINT_MAX_BYTES: usize = 5;
+
+/// パケットの最大長。ステータスの応答にはアイコン画像が含まれるので余裕を持たせる
+const MAX_PACKET_LENGTH: i32 = 2 * 1024 * 1024;
+
+pub fn write_varint(buf: &mut Vec<u8>, value: i32) {
+    let mut value = value as u32;
+    loop {
+        if value & !0x7F == 0 {
+            buf.push(value as u8);
+            return;
+        }
+        buf.push((value & 0x7F) as u8 | 0x80);
+        value >>= 7;
+    }
+}
+
+pub fn read_varint(reader: &mut impl Read) -> io::Result<i32> {
+    let mut value = 0u32;
+
+    for i in 0..VARINT_MAX_BYTES {
+        let mut byte = [0u8];
+        reader.read_exact(&mut byte)?;
+
+        value |= ((byte[0] & 0x7F) as u32) << (7 * i);
+        if byte[0] & 0x80 == 0 {
+            return Ok(value as i32);
+        }
+    }
+
+    Err(io::Error::new(
+        io::ErrorKind::InvalidData,
+        "VarIntが長すぎます",
+    ))
+}
+
+pub fn write_string(buf: &mut Vec<u8>, value: &str) {
+    write_varint(buf, value.len() as i32);
+    buf.extend_from_slice(value.as_bytes());
+}
+
+pub fn read_string(reader: &mut impl Read) -> io::Result<String> {
+    let len = read_varint(reader)?;
+    if !(0..=MAX_PACKET_LENGTH).contains(&len) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "文字列の長さが不正です",
+        ));
+    }
+
+    let mut buf = vec![0u8; len as usize];
+    reader.read_exact(&mut buf)?;
+
+    String::from_utf8(buf).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+}
+
+/// Write a packet with its length prefix.
+pub fn write_packet(writer: &mut impl Write, id: i32, data: &[u8]) -> io::Result<()> {
+    let mut body = Vec::with_capacity(data.len() + VARINT_MAX_BYTES);
+    write_varint(&mut body, id);
+    body.extend_from_slice(data);
+
+    let mut packet = Vec::with_capacity(body.len() + VARINT_MAX_BYTES);
+    write_varint(&mut packet, body.len() as i32);
+    packet.extend_from_slice(&body);
+
+    writer.write_all(&packet)
+}
+
+/// Read a packet and return its ID and data.
+pub fn read_packet(reader: &mut impl Read) -> io::Result<(i32, Vec<u8>)> {
+    let len = read_varint(reader)?;
+    if !(1..=MAX_PACKET_LENGTH).contains(&len) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("パケット長が不正です: {len}"),
+        ));
+    }
+
+    let mut buf = vec![0u8; len as usize];
+    reader.read_exact(&mut buf)?;
+
+    let mut data = buf.as_slice();
+    let id = read_varint(&mut data)?;
+
+    Ok((id, data.to_vec()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn varint_roundtrip() {
+        for (value, bytes) in [
+            (0, vec![0x00]),
+            (1, vec![0x01]),
+            (127, vec![0x7F]),
+            (128, vec![0x80, 0x01]),
+            (25565, vec![0xDD, 0xC7, 0x01]),
+            (-1, vec![0xFF, 0xFF, 0xFF, 0xFF, 0x0F]),
+        ] {
+            let mut buf = Vec::new();
+            write_varint(&mut buf, value);
+            assert_eq!(buf, bytes);
+            assert_eq!(read_varint(&mut buf.as_slice()).unwrap(), value);
+        }
+    }
+
+    #[test]
+    fn packet_roundtrip() {
+        let mut data = Vec::new();
+        write_string(&mut data, "こんにちは");
+
+        let mut buf = Vec::new();
+        write_packet(&mut buf, 0x00, &data).unwrap();
+
+        let (id, data) = read_packet(&mut buf.as_slice()).unwrap();
+        assert_eq!(id, 0x00);
+        assert_eq!(read_string(&mut data.as_slice()).unwrap(), "こんにちは");
+    }
+}
@@ -0,0 +1,312 @@
+//! Client for the Source RCON protocol used by Minecraft servers.
+//!
+//! <https://developer.valvesoftware.com/wiki/Source_RCON_Protocol>
+use std::{
+    io::{self, Read, Write},
+    net::{TcpStream, ToSocketAddrs},
+    time::Duration,
+};
+
+const SERVERDATA_AUTH: i32 = 3;
+const SERVERDATA_AUTH_RESPONSE: i32 = 2;
+const SERVERDATA_EXECCOMMAND: i32 = 2;
+
+/// Minecraftは未知の種類のパケットに対して `Unknown request` を返すので、
+/// 分割された応答の終わりを知るために利用する。
+const END_MARKER_TYPE: i32 = 100;
+
+/// Minecraftが応答を分割する文字数。これより短い応答は分割されていない
+const MAX_RESPONSE_BODY_LENGTH: usize = 4096;
+
+/// 受信するパケットの最大長。応答は文字数で分割されるので、UTF-8では1文字3バイトまで増える
+const MAX_RESPONSE_PACKET_LENGTH: usize = MAX_RESPONSE_BODY_LENGTH * 3 + 10;
+
+/// Minecraftが受け付けるコマンドの最大バイト数
+const MAX_REQUEST_BODY_LENGTH: usize = 1446;
+
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+struct Packet {
+    id: i32,
+    kind: i32,
+    body: String,
+}
+
+pub struct RconClient {
+    stream: TcpStream,
+    next_id: i32,
+}
+
+impl RconClient {
+    /// Connect to the server and authenticate with the password.
+    pub fn connect(addr: impl ToSocketAddrs, password: &str) -> io::Result<RconClient> {
+        let addr = addr.to_socket_addrs()?.next().ok_or_else(|| {
+            io::Error::new(io::ErrorKind::NotFound, "RCONの接続先が見つかりません")
+        })?;
+
+        let stream = TcpStream::connect_timeout(&addr, TIMEOUT)?;
+        stream.set_read_timeout(Some(TIMEOUT))?;
+        stream.set_write_timeout(Some(TIMEOUT))?;
+
+        let mut client = RconClient { stream, next_id: 1 };
+
+        let id = client.send(SERVERDATA_AUTH, password)?;
+        let res = client.receive()?;
+        if res.kind != SERVERDATA_AUTH_RESPONSE || res.id != id {
+            return Err(io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                "RCONの認証に失敗しました。パスワードを確認してください",
+            ));
+        }
+
+        Ok(client)
+    }
+
+    /// Execute a command and get its output.
+    pub fn command(&mut self, command: &str) -> io::Result<String> {
+        let id = self.send(SERVERDATA_EXECCOMMAND, command)?;
+
+        // Minecraftはパケットを1つずつ読むので、続けて送ると2つ目のパケットを正しく読めない。
+        // 最初の応答を受け取ってから終わりの目印を送る
+        let first = loop {
+            let res = self.receive()?;
+            if res.id == id {
+                break res;
+            }
+        };
+        if first.body.chars().count() < MAX_RESPONSE_BODY_LENGTH {
+            return Ok(first.body);
+        }
+
+        let end_id = self.send(END_MARKER_TYPE, "")?;
+        let mut output = first.body;
+        loop {
+            let res = self.receive()?;
+            if res.id == end_id {
+                break;
+            }
+            if res.id == id {
+                output.push_str(&res.body);
+            }
+        }
+
+        Ok(output)
+    }
+
+    fn send(&mut self, kind: i32, body: &str) -> io::Result<i32> {
+        if body.len() > MAX_REQUEST_BODY_LENGTH {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "コマンドが長すぎます",
+            ));
+        }
+
+        let id = self.next_id;
+        self.next_id = self.next_id.wrapping_add(1).max(1);
+
+        let packet = Packet {
+            id,
+            kind,
+            body: body.to_string(),
+        };
+        self.stream.write_all(&packet.encode())?;
+
+        Ok(id)
+    }
+
+    fn receive(&mut self) -> io::Result<Packet> {
+        let mut len = [0u8; 4];
+        self.stream.read_exact(&mut len)?;
+
+        let len = i32::from_le_bytes(len);
+        if !(10..=MAX_RESPONSE_PACKET_LENGTH as i32).contains(&len) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("RCONのパケット長が不正です: {len}"),
+            ));
+        }
+
+        let mut buf = vec![0u8; len as usize];
+        self.stream.read_exact(&mut buf)?;
+
+        Packet::decode(&buf)
+    }
+}
+
+impl Packet {
+    fn encode(&self) -> Vec<u8> {
+        let body = self.body.as_bytes();
+        let len = body.len() + 10;
+
+        let mut buf = Vec::with_capacity(len + 4);
+        buf.extend_from_slice(&(len as i32).to_le_bytes());
+        buf.extend_from_slice(&self.id.to_le_bytes());
+        buf.extend_from_slice(&self.kind.to_le_bytes());
+        buf.extend_from_slice(body);
+        buf.extend_from_slice(&[0, 0]);
+
+        buf
+    }
+
+    /// Decode a packet without the length field.
+    fn decode(buf: &[u8]) -> io::Result<Packet> {
+        let id = i32::from_le_bytes(buf[0..4].try_into().unwrap());
+        let kind = i32::from_le_bytes(buf[4..8].try_into().unwrap());
+        let body = &buf[8..buf.len() - 2];
+
+        Ok(Packet {
+            id,
+            kind,
+            body: String::from_utf8_lossy(body).into_owned(),
+        })
+    }
+}
+
+/// Remove formatting codes such as `§a` from the output.
+pub fn strip_formatting_codes(s: &str) -> String {
+    let mut result = String::with_capacity(s.len());
+    let mut chars = s.chars();
+
+    while let Some(c) = chars.next() {
+        if c == '§' {
+            chars.next();
+        } else {
+            result.push(c);
+        }
+    }
+
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::TcpListener;
+    use std::thread;
+
+    /// Minimal fake RCON server which answers `list` and `help` and rejects wrong passwords.
+    fn fake_server(password: &'static str) -> u16 {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+
+        thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+
+            let read_packet = |stream: &mut TcpStream| -> Option<Packet> {
+                let mut len = [0u8; 4];
+                stream.read_exact(&mut len).ok()?;
+                let mut buf = vec![0u8; i32::from_le_bytes(len) as usize];
+                stream.read_exact(&mut buf).ok()?;
+                Packet::decode(&buf).ok()
+            };
+            let write_packet = |stream: &mut TcpStream, id: i32, kind: i32, body: &str| {
+                let packet = Packet {
+                    id,
+                    kind,
+                    body: body.to_string(),
+                };
+                stream.write_all(&packet.encode()).unwrap();
+            };
+
+            while let Some(packet) = read_packet(&mut stream) {
+                match packet.kind {
+                    SERVERDATA_AUTH => {
+                        let id = if packet.body == password {
+                            packet.id
+                        } else {
+                            -1
+                        };
+                        write_packet(&mut stream, id, SERVERDATA_AUTH_RESPONSE, "");
+                    }
+                    SERVERDATA_EXECCOMMAND if packet.body == "list" => {
+                        write_packet(
+                            &mut stream,
+                            packet.id,
+                            0,
+                            "There are 1 of a max of 20 players online: §aSteve",
+                        );
+                    }
+                    SERVERDATA_EXECCOMMAND if packet.body == "help" => {
+                        // 応答が分割されていても結合されることを確認する
+                        let help = "a".repeat(MAX_RESPONSE_BODY_LENGTH);
+                        write_packet(&mut stream, packet.id, 0, &help);
+                        write_packet(&mut stream, packet.id, 0, "bcd");
+                    }
+                    SERVERDATA_EXECCOMMAND if packet.body == "help ja" => {
+                        // 応答は文字数で分割されるので、マルチバイト文字では4096バイトを超える
+                        let help = "あ".repeat(MAX_RESPONSE_BODY_LENGTH);
+                        write_packet(&mut stream, packet.id, 0, &help);
+                        write_packet(&mut stream, packet.id, 0, "いう");
+                    }
+                    SERVERDATA_EXECCOMMAND => {
+                        write_packet(&mut stream, packet.id, 0, "Unknown command");
+                    }
+                    kind => {
+                        write_packet(
+                            &mut stream,
+                            packet.id,
+                            0,
+                            &format!("Unknown request {kind:x}"),
+                        );
+                    }
+                }
+            }
+        });
+
+        port
+    }
+
+    #[test]
+    fn execute_command() {
+        let port = fake_server("secret");
+
+        let mut client = RconClient::connect(("127.0.0.1", port), "secret").unwrap();
+        let output = client.command("list").unwrap();
+
+        assert_eq!(output, "There are 1 of a max of 20 players online: §aSteve");
+        assert_eq!(
+            strip_formatting_codes(&output),
+            "There are 1 of a max of 20 players online: Steve"
+        );
+        assert_eq!(client.command("foo").unwrap(), "Unknown command");
+
+        let help = client.command("help").unwrap();
+        assert_eq!(help.len(), MAX_RESPONSE_BODY_LENGTH + 3);
+        assert!(help.ends_with("abcd"));
+        // 終わりの目印の応答が次のコマンドの出力に混ざらない
+        assert_eq!(client.command("foo").unwrap(), "Unknown command");
+    }
+
+    #[test]
+    fn receive_multibyte_output() {
+        let port = fake_server("secret");
+
+        let mut client = RconClient::connect(("127.0.0.1", port), "secret").unwrap();
+        let help = client.command("help ja").unwrap();
+
+        assert_eq!(help.chars().count(), MAX_RESPONSE_BODY_LENGTH + 2);
+        assert!(help.ends_with("あいう"));
+        assert_eq!(client.command("foo").unwrap(), "Unknown command");
+    }
+
+    #[test]
+    fn reject_too_long_command() {
+        let port = fake_server("secret");
+
+        let mut client = RconClient::connect(("127.0.0.1", port), "secret").unwrap();
+        let command = format!("say {}", "a".repeat(MAX_REQUEST_BODY_LENGTH));
+
+        let err = client.command(&command).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn reject_wrong_password() {
+        let port = fake_server("secret");
+
+        let err = RconClient::connect(("127.0.0.1", port), "wrong")
+            .err()
+            .unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::PermissionDenied);
+    }
+}
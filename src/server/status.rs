@@ -0,0 +1,180 @@
+//! Server List Ping
+//!
+//! <https://minecraft.wiki/w/Java_Edition_protocol/Server_List_Ping>
+use super::protocol::*;
+use serde_derive::Deserialize;
+use std::{
+    io,
+    net::{TcpStream, ToSocketAddrs},
+    time::Duration,
+};
+
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+/// ハンドシェイクで送信するプロトコルバージョン。
+/// ステータスの取得ではどのバージョンでも応答が返る
+const PROTOCOL_VERSION: i32 = -1;
+
+/// ハンドシェイク後の状態: ステータス
+const NEXT_STATE_STATUS: i32 = 1;
+
+/// Response of the status request.
+#[derive(Deserialize, Debug)]
+pub struct ServerStatus {
+    pub version: StatusVersion,
+    pub players: StatusPlayers,
+    #[serde(default)]
+    pub description: serde_json::Value,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct StatusVersion {
+    pub name: String,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct StatusPlayers {
+    pub max: i32,
+    pub online: i32,
+    #[serde(default)]
+    pub sample: Vec<StatusPlayer>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct StatusPlayer {
+    pub name: String,
+}
+
+impl ServerStatus {
+    /// Get the MOTD as plain text.
+    pub fn motd(&self) -> String {
+        super::strip_formatting_codes(&component_text(&self.description))
+    }
+}
+
+/// Flatten a text component into plain text.
+fn component_text(component: &serde_json::Value) -> String {
+    use serde_json::Value;
+
+    match component {
+        Value::String(s) => s.clone(),
+        Value::Array(components) => components.iter().map(component_text).collect(),
+        Value::Object(object) => {
+            let mut text = object
+                .get("text")
+                .and_then(Value::as_str)
+                .unwrap_or_default()
+                .to_string();
+            if let Some(extra) = object.get("extra") {
+                text.push_str(&component_text(extra));
+            }
+            text
+        }
+        _ => String::new(),
+    }
+}
+
+/// Query the server status with Server List Ping.
+pub fn query_status(host: &str, port: u16) -> io::Result<ServerStatus> {
+    let addr = (host, port).to_socket_addrs()?.next().ok_or_else(|| {
+        io::Error::new(io::ErrorKind::NotFound, "サーバのアドレスが見つかりません")
+    })?;
+
+    let mut stream = TcpStream::connect_timeout(&addr, TIMEOUT)?;
+    stream.set_read_timeout(Some(TIMEOUT))?;
+    stream.set_write_timeout(Some(TIMEOUT))?;
+
+    // Handshake
+    let mut handshake = Vec::new();
+    write_varint(&mut handshake, PROTOCOL_VERSION);
+    write_string(&mut handshake, host);
+    handshake.extend_from_slice(&port.to_be_bytes());
+    write_varint(&mut handshake, NEXT_STATE_STATUS);
+    write_packet(&mut stream, 0x00, &handshake)?;
+
+    // Status Request
+    write_packet(&mut stream, 0x00, &[])?;
+
+    // Status Response
+    let (id, data) = read_packet(&mut stream)?;
+    if id != 0x00 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("予期しないパケットを受信しました: {id:#x}"),
+        ));
+    }
+    let json = read_string(&mut data.as_slice())?;
+
+    serde_json::from_str(&json).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::TcpListener;
+    use std::thread;
+
+    fn fake_server(response: &'static str) -> u16 {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+
+        thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+
+            // Handshake
+            let (id, data) = read_packet(&mut stream).unwrap();
+            assert_eq!(id, 0x00);
+            let mut data = data.as_slice();
+            read_varint(&mut data).unwrap();
+            assert_eq!(read_string(&mut data).unwrap(), "127.0.0.1");
+
+            // Status Request
+            let (id, _) = read_packet(&mut stream).unwrap();
+            assert_eq!(id, 0x00);
+
+            let mut body = Vec::new();
+            write_string(&mut body, response);
+            write_packet(&mut stream, 0x00, &body).unwrap();
+        });
+
+        port
+    }
+
+    #[test]
+    fn query_server_status() {
+        let port = fake_server(
+            r#"{
+                "version": {"name": "1.21.4", "protocol": 769},
+                "players": {"max": 20, "online": 1, "sample": [{"name": "Steve", "id": "00000000-0000-0000-0000-000000000000"}]},
+                "description": {"text": "§aA ", "extra": [{"text": "Minecraft Server"}]}
+            }"#,
+        );
+
+        let status = query_status("127.0.0.1", port).unwrap();
+        assert_eq!(status.version.name, "1.21.4");
+        assert_eq!(status.players.online, 1);
+        assert_eq!(status.players.max, 20);
+        assert_eq!(status.players.sample[0].name, "Steve");
+        assert_eq!(status.motd(), "A Minecraft Server");
+    }
+
+    #[test]
+    fn query_server_status_with_string_description() {
+        let port = fake_server(
+            r#"{"version": {"name": "1.8", "protocol": 47}, "players": {"max": 10, "online": 0}, "description": "Hello"}"#,
+        );
+
+        let status = query_status("127.0.0.1", port).unwrap();
+        assert!(status.players.sample.is_empty());
+        assert_eq!(status.motd(), "Hello");
+    }
+
+    #[test]
+    fn fail_when_server_is_down() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        drop(listener);
+
+        assert!(query_status("127.0.0.1", port).is_err());
+    }
+}
@@ -0,0 +1,294 @@
+//! Wake-on-connect: answers Server List Ping while the server is stopped,
+//! and reports a player trying to join.
+//!
+//! <https://minecraft.wiki/w/Java_Edition_protocol/Server_List_Ping>
+use super::protocol::*;
+use serde_json::json;
+use std::{
+    io,
+    net::{TcpListener, TcpStream},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+/// 停止の要求を確認する間隔
+const POLL_INTERVAL: Duration = Duration::from_millis(200);
+
+/// 1つの接続の読み書きを待つ時間
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+/// ハンドシェイク後の状態: ステータス
+const NEXT_STATE_STATUS: i32 = 1;
+
+/// ハンドシェイク後の状態: ログイン
+const NEXT_STATE_LOGIN: i32 = 2;
+
+/// ハンドシェイク後の状態: 別のサーバからの転送
+const NEXT_STATE_TRANSFER: i32 = 3;
+
+/// Messages shown to the players while the server is sleeping.
+pub struct WakeMessages {
+    /// サーバ一覧に表示するMOTD
+    pub motd: String,
+    /// 接続したプレイヤーに表示する切断メッセージ
+    pub kick_message: String,
+}
+
+/// A listener on the server port while the server is stopped.
+pub struct WakeListener {
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl WakeListener {
+    /// Start listening on the port.
+    ///
+    /// `on_wake` is called with the player name when a player tries to join.
+    /// The port is released before `on_wake` is called.
+    pub fn start(
+        port: u16,
+        messages: WakeMessages,
+        on_wake: impl FnOnce(String) + Send + 'static,
+    ) -> io::Result<WakeListener> {
+        let listener = TcpListener::bind(("0.0.0.0", port))?;
+        // 停止の要求を確認できるように、ブロックしない
+        listener.set_nonblocking(true)?;
+
+        let stop = Arc::new(AtomicBool::new(false));
+        let thread = {
+            let stop = Arc::clone(&stop);
+            thread::spawn(move || {
+                let player = listen(listener, &messages, &stop);
+                if let Some(player) = player {
+                    on_wake(player);
+                }
+            })
+        };
+
+        Ok(WakeListener {
+            stop,
+            thread: Some(thread),
+        })
+    }
+
+    /// Stop listening and wait until the port is released.
+    ///
+    /// Must not be called from `on_wake`. Dropping the listener stops it without waiting.
+    pub fn stop(mut self) {
+        self.stop.store(true, Ordering::SeqCst);
+
+        if let Some(thread) = self.thread.take() {
+            thread.join().ok();
+        }
+    }
+}
+
+impl Drop for WakeListener {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::SeqCst);
+    }
+}
+
+/// Accept connections until a player tries to join or stop is requested.
+///
+/// Returns the name of the player. The listener is closed when this returns.
+fn listen(listener: TcpListener, messages: &WakeMessages, stop: &AtomicBool) -> Option<String> {
+    while !stop.load(Ordering::SeqCst) {
+        let stream = match listener.accept() {
+            Ok((stream, _)) => stream,
+            Err(err) if err.kind() == io::ErrorKind::WouldBlock => {
+                thread::sleep(POLL_INTERVAL);
+                continue;
+            }
+            Err(err) => {
+                println!("接続を受け付けられませんでした: {err}");
+                thread::sleep(POLL_INTERVAL);
+                continue;
+            }
+        };
+
+        match handle_connection(stream, messages) {
+            Ok(Some(player)) => return Some(player),
+            Ok(None) => {}
+            // 不正な接続は無視する
+            Err(_) => {}
+        }
+    }
+
+    None
+}
+
+/// Handle one connection. Returns the player name if it is a login attempt.
+fn handle_connection(mut stream: TcpStream, messages: &WakeMessages) -> io::Result<Option<String>> {
+    stream.set_nonblocking(false)?;
+    stream.set_read_timeout(Some(TIMEOUT))?;
+    stream.set_write_timeout(Some(TIMEOUT))?;
+
+    // Handshake
+    let (id, data) = read_packet(&mut stream)?;
+    if id != 0x00 {
+        return Ok(None);
+    }
+    let mut data = data.as_slice();
+    let protocol = read_varint(&mut data)?;
+    let _address = read_string(&mut data)?;
+    let mut port = [0u8; 2];
+    io::Read::read_exact(&mut data, &mut port)?;
+    let next_state = read_varint(&mut data)?;
+
+    match next_state {
+        NEXT_STATE_STATUS => {
+            answer_status(&mut stream, protocol, &messages.motd)?;
+            Ok(None)
+        }
+        NEXT_STATE_LOGIN | NEXT_STATE_TRANSFER => {
+            // Login Start
+            let (id, data) = read_packet(&mut stream)?;
+            if id != 0x00 {
+                return Ok(None);
+            }
+            // 名前は認証されていないので、Discordに送る前に検証する
+            let player = read_string(&mut data.as_slice())?;
+            if !is_valid_player_name(&player) {
+                return Ok(None);
+            }
+
+            // Disconnect (login)
+            let mut body = Vec::new();
+            write_string(
+                &mut body,
+                &json!({ "text": messages.kick_message }).to_string(),
+            );
+            write_packet(&mut stream, 0x00, &body)?;
+
+            Ok(Some(player))
+        }
+        _ => Ok(None),
+    }
+}
+
+/// Whether the name can be a Minecraft player name: 1 to 16 letters, digits or `_`.
+fn is_valid_player_name(name: &str) -> bool {
+    (1..=16).contains(&name.len()) && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Answer the status request and the ping request.
+fn answer_status(stream: &mut TcpStream, protocol: i32, motd: &str) -> io::Result<()> {
+    // Status Request
+    let (id, _) = read_packet(stream)?;
+    if id != 0x00 {
+        return Ok(());
+    }
+
+    // クライアントと同じプロトコルバージョンを返して、互換性のないサーバと表示されないようにする
+    let status = json!({
+        "version": { "name": "hone", "protocol": protocol },
+        "players": { "max": 0, "online": 0 },
+        "description": { "text": motd },
+    });
+    let mut body = Vec::new();
+    write_string(&mut body, &status.to_string());
+    write_packet(stream, 0x00, &body)?;
+
+    // Ping Request には同じ値を返す
+    let (id, data) = read_packet(stream)?;
+    if id == 0x01 {
+        write_packet(stream, 0x01, &data)?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::server::query_status;
+    use std::sync::mpsc;
+
+    fn free_port() -> u16 {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        listener.local_addr().unwrap().port()
+    }
+
+    fn messages() -> WakeMessages {
+        WakeMessages {
+            motd: "休止中".to_string(),
+            kick_message: "起動しています".to_string(),
+        }
+    }
+
+    #[test]
+    fn answer_status_while_sleeping() {
+        let port = free_port();
+        let listener = WakeListener::start(port, messages(), |_| {}).unwrap();
+
+        let status = query_status("127.0.0.1", port).unwrap();
+        assert_eq!(status.motd(), "休止中");
+        assert_eq!(status.players.online, 0);
+
+        listener.stop();
+        // ポートが解放されている
+        assert!(TcpListener::bind(("0.0.0.0", port)).is_ok());
+    }
+
+    /// Send the handshake and Login Start as a client.
+    fn login(port: u16, player: &str) -> TcpStream {
+        let mut stream = TcpStream::connect(("127.0.0.1", port)).unwrap();
+        let mut handshake = Vec::new();
+        write_varint(&mut handshake, 769);
+        write_string(&mut handshake, "localhost");
+        handshake.extend_from_slice(&port.to_be_bytes());
+        write_varint(&mut handshake, NEXT_STATE_LOGIN);
+        write_packet(&mut stream, 0x00, &handshake).unwrap();
+
+        let mut login = Vec::new();
+        write_string(&mut login, player);
+        login.extend_from_slice(&[0u8; 16]);
+        write_packet(&mut stream, 0x00, &login).unwrap();
+
+        stream
+    }
+
+    #[test]
+    fn wake_on_login() {
+        let port = free_port();
+        let (tx, rx) = mpsc::channel();
+        let _listener = WakeListener::start(port, messages(), move |player| {
+            tx.send(player).unwrap();
+        })
+        .unwrap();
+
+        let mut stream = login(port, "Steve");
+        let (id, data) = read_packet(&mut stream).unwrap();
+        assert_eq!(id, 0x00);
+        assert!(read_string(&mut data.as_slice())
+            .unwrap()
+            .contains("起動しています"));
+
+        assert_eq!(rx.recv_timeout(TIMEOUT).unwrap(), "Steve");
+        // 起動する前にポートを解放している
+        assert!(TcpListener::bind(("0.0.0.0", port)).is_ok());
+    }
+
+    #[test]
+    fn ignore_invalid_player_name() {
+        let port = free_port();
+        let (tx, rx) = mpsc::channel();
+        let listener = WakeListener::start(port, messages(), move |player| {
+            tx.send(player).unwrap();
+        })
+        .unwrap();
+
+        login(port, "`@everyone`");
+        login(port, "");
+        assert!(rx.recv_timeout(Duration::from_secs(1)).is_err());
+
+        listener.stop();
+        assert!(is_valid_player_name("Steve_2"));
+        assert!(!is_valid_player_name("a_name_longer_than_16"));
+    }
+}
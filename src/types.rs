@@ -1,6 +1,79 @@
+use crate::server::LogEvent;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
 pub enum ServerMessage {
     Done,
     Exit,
+    /// `save-all` によるワールドの保存が完了した
+    Saved,
     Info(String),
+    /// ログから読み取ったイベント
+    Event(LogEvent),
     Error(String),
 }
+
+/// サーバが停止した理由
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StopReason {
+    /// `!mcend` などのコマンドによる停止
+    Command,
+    /// 自動停止
+    AutoStop,
+    /// honeの終了に伴う停止
+    Shutdown,
+    /// 再起動のための停止
+    Restart,
+    /// `!mckill` による強制終了
+    Kill,
+    /// 停止が要求されていないのに終了した
+    Crash,
+}
+
+/// Records why the server is going to stop.
+///
+/// The reason must be recorded before sending `stop` to the server.
+/// If no reason is recorded when the server exits, the exit is treated as a crash.
+#[derive(Clone, Default)]
+pub struct StopReasonRecorder(Arc<Mutex<Option<StopReason>>>);
+
+impl StopReasonRecorder {
+    /// Record the reason. An already recorded reason is kept.
+    pub fn record(&self, reason: StopReason) {
+        let mut current = self.0.lock().unwrap();
+        if current.is_none() {
+            *current = Some(reason);
+        }
+    }
+
+    /// Take the recorded reason, or `StopReason::Crash` if nothing is recorded.
+    pub fn take(&self) -> StopReason {
+        self.0.lock().unwrap().take().unwrap_or(StopReason::Crash)
+    }
+
+    /// Record the reason, replacing the already recorded one.
+    pub fn overwrite(&self, reason: StopReason) {
+        *self.0.lock().unwrap() = Some(reason);
+    }
+
+    /// Forget the recorded reason.
+    pub fn clear(&self) {
+        *self.0.lock().unwrap() = None;
+    }
+}
+
+/// The running server process.
+pub struct ServerProcess {
+    pub pid: u32,
+    /// `stop` を送信した日時
+    pub stop_requested_at: Option<Instant>,
+}
+
+impl ServerProcess {
+    pub fn new(pid: u32) -> ServerProcess {
+        ServerProcess {
+            pid,
+            stop_requested_at: None,
+        }
+    }
+}